@@ -4,7 +4,7 @@
 #![warn(clippy::nursery)]
 //#![warn(clippy::cargo)]
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use ndarray::prelude::*;
 use nom::{
     bytes::complete::tag,
@@ -23,10 +23,27 @@ fn main() -> Result<()> {
     #[cfg(feature = "dhat-heap")]
     let _profiler = dhat::Profiler::new_heap();
 
-    println!(
-        "Answer: {:?}",
-        process(&std::fs::read_to_string("inputs/day05.txt")?)?
-    );
+    let input = std::fs::read_to_string("inputs/day05.txt")?;
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        None => println!("Answer: {:?}", process(&input)?),
+        Some("pgm") => {
+            let path = args.next().unwrap_or_else(|| "day05.pgm".to_owned());
+            write_pgm(&vent_grid(&input)?, &path).with_context(|| format!("Writing {path}"))?;
+        }
+        Some("heatmap") => {
+            let grid = vent_grid(&input)?;
+            let from = args.next().map_or(Ok((0, 0)), |arg| parse_point(&arg))?;
+            let to = args.next().map_or_else(
+                || Ok((grid.dim().0 - 1, grid.dim().1 - 1)),
+                |arg| parse_point(&arg),
+            )?;
+            print!("{}", heatmap(&grid, from, to));
+        }
+        Some(cmd) => {
+            bail!("Unknown command {cmd:?}, expected `pgm [FILE]` or `heatmap [X1,Y1 X2,Y2]`")
+        }
+    }
 
     Ok(())
 }
@@ -70,6 +87,14 @@ dhat: The data has been saved to dhat-heap.json, and is viewable with dhat/dh_vi
 */
 
 fn process(input: &str) -> Result<usize> {
+    let grid = vent_grid(input)?;
+    let answer = grid.iter().filter(|v| **v > 1).count();
+
+    Ok(answer)
+}
+
+// Count how many vent lines cross each point. The grid is indexed [x, y].
+fn vent_grid(input: &str) -> Result<Array2<u8>> {
     let lines: Vec<Line> = finish(all_consuming(lines)(input))?;
 
     let grid_size = lines
@@ -108,12 +133,44 @@ fn process(input: &str) -> Result<usize> {
         }
     }
 
-    let answer = grid.iter().filter(|v| **v > 1).count();
+    Ok(grid)
+}
 
-    // ndarray slices are [row, column], not [x, y]
-    //dbg!(grid.reversed_axes(), answer);
+// Binary greyscale image, one pixel per point; the brightest pixel is the most overlaps
+fn write_pgm(grid: &Array2<u8>, path: &str) -> Result<()> {
+    use std::io::Write;
 
-    Ok(answer)
+    let (width, height) = grid.dim();
+    let max_value = grid.iter().copied().max().unwrap_or(0).max(1);
+
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    write!(out, "P5\n{width} {height}\n{max_value}\n")?;
+    // ndarray slices are [row, column], not [x, y], so rows of the image are columns here
+    for row in grid.columns() {
+        out.write_all(&row.to_vec())?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+// The region is inclusive, and clipped to the grid. Uses the same notation as the puzzle
+// text: '.' for no lines, otherwise the number of lines crossing that point.
+fn heatmap(grid: &Array2<u8>, (x1, y1): (Coord, Coord), (x2, y2): (Coord, Coord)) -> String {
+    let (width, height) = grid.dim();
+    let xx = x1.min(x2)..=x1.max(x2).min(width - 1);
+    let yy = y1.min(y2)..=y1.max(y2).min(height - 1);
+
+    let mut out = String::new();
+    for y in yy {
+        out.extend(xx.clone().map(|x| match grid[[x, y]] {
+            0 => '.',
+            n @ 1..=9 => char::from(b'0' + n),
+            _ => '#',
+        }));
+        out.push('\n');
+    }
+    out
 }
 
 type Coord = usize;
@@ -132,6 +189,10 @@ fn point(input: &str) -> IResult<&str, (Coord, Coord)> {
     })(input)
 }
 
+fn parse_point(input: &str) -> Result<(Coord, Coord)> {
+    finish(all_consuming(point)(input)).with_context(|| format!("Invalid point {input:?}"))
+}
+
 fn line(input: &str) -> IResult<&str, Line> {
     map(
         separated_pair(point, delimited(space0, tag("->"), space0), point),
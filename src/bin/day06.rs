@@ -4,9 +4,11 @@
 #![warn(clippy::nursery)]
 //#![warn(clippy::cargo)]
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 //use ndarray::prelude::*;
 use nom::{bytes::complete::tag, character::complete::u8, multi::separated_list1, IResult};
+use num_bigint::BigUint;
+use std::io::Write;
 
 #[cfg(feature = "dhat-heap")]
 #[global_allocator]
@@ -16,14 +18,57 @@ fn main() -> Result<()> {
     #[cfg(feature = "dhat-heap")]
     let _profiler = dhat::Profiler::new_heap();
 
-    println!(
-        "Answer: {:?}",
-        process(&std::fs::read_to_string("inputs/day06.txt")?)?
-    );
+    let mut input = None;
+    let mut lifecycle = Lifecycle::LANTERNFISH;
+    let mut command = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--spawn-interval" => lifecycle.spawn_interval = option_value(&arg, args.next())?,
+            "--newborn-delay" => lifecycle.newborn_delay = option_value(&arg, args.next())?,
+            "--timers" => input = Some(option_value::<String>(&arg, args.next())?),
+            _ => command.push(arg),
+        }
+    }
+    let input = match input {
+        Some(timers) => timers,
+        None => std::fs::read_to_string("inputs/day06.txt")?,
+    };
+
+    match command.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => println!("Answer: {:?}", process(&input, lifecycle)?),
+        ["forecast", days] => {
+            let days = days
+                .parse()
+                .with_context(|| format!("Invalid number of days {days:?}"))?;
+            println!("Answer: {}", forecast(&input, lifecycle, days)?);
+        }
+        ["series", days] => {
+            let days = days
+                .parse()
+                .with_context(|| format!("Invalid number of days {days:?}"))?;
+            series(&input, lifecycle, days, &mut std::io::stdout().lock())?;
+        }
+        _ => bail!(
+            "Unknown command {command:?}, expected `forecast DAYS` or `series DAYS`, \
+            with options `--spawn-interval N`, `--newborn-delay N`, `--timers T,T,...`"
+        ),
+    }
 
     Ok(())
 }
 
+fn option_value<T>(name: &str, value: Option<String>) -> Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let value = value.ok_or_else(|| anyhow!("Missing value for {name}"))?;
+    value
+        .parse()
+        .with_context(|| format!("Invalid value {value:?} for {name}"))
+}
+
 // Convert nom's IResult to anyhow's Result, discarding any remaining input
 fn finish<A>(parsed: IResult<&str, A>) -> Result<A> {
     parsed
@@ -33,44 +78,69 @@ fn finish<A>(parsed: IResult<&str, A>) -> Result<A> {
 
 // Hope there's never more than this many fish with any given timer value
 type FishCount = u64;
-// Fish timer is between 0 and 8
-const TIMER_END: usize = 9;
 
-type SchoolStorage = [FishCount; TIMER_END];
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Copy)]
+struct Lifecycle {
+    // Days between spawns, so a fish resets to spawn_interval - 1 after spawning
+    spawn_interval: usize,
+    // Extra days a newborn waits before its first spawn interval starts
+    newborn_delay: usize,
+}
+
+impl Lifecycle {
+    // Timer resets to 6, and newborns start at 8
+    const LANTERNFISH: Self = Self {
+        spawn_interval: 7,
+        newborn_delay: 2,
+    };
+
+    // Fish timer is between 0 and timer_end - 1, which is the newborn's timer
+    const fn timer_end(self) -> usize {
+        self.spawn_interval + self.newborn_delay
+    }
+}
+
+#[derive(Debug)]
 struct School {
-    counts: SchoolStorage,
+    counts: Vec<FishCount>,
     zero_index: usize,
+    lifecycle: Lifecycle,
 }
 
 impl School {
-    fn new(fishes: Vec<u8>) -> Self {
-        let mut counts = SchoolStorage::default();
+    fn new(lifecycle: Lifecycle, fishes: Vec<u8>) -> Result<Self> {
+        ensure!(
+            lifecycle.spawn_interval > 0,
+            "Invalid lifecycle, spawn interval must be at least 1 day"
+        );
+        let mut counts = vec![0; lifecycle.timer_end()];
 
         for timer in fishes {
             let timer = usize::from(timer);
-            assert!(
-                timer < TIMER_END,
-                "Invalid input, fish timer too large: {}",
-                timer
+            ensure!(
+                timer < counts.len(),
+                "Invalid input, fish timer too large: {timer}"
             );
             counts[timer] += 1;
         }
 
-        Self {
+        Ok(Self {
             counts,
             zero_index: 0,
-        }
+            lifecycle,
+        })
     }
 
     fn advance(&mut self) -> Result<()> {
-        let of_day = |i| (self.zero_index + i) % TIMER_END;
+        let timer_end = self.counts.len();
+        let of_day = |i| (self.zero_index + i) % timer_end;
 
         // Keep the zero count as is; it will become the new fish with timer 8
-        // self.counts[of_day((TIMER_END - 1) + 1)] = self.counts[self.zero_index];
+        // self.counts[of_day((timer_end - 1) + 1)] = self.counts[self.zero_index];
 
         // And also add the zero count to (the upcoming) day 6
-        self.counts[of_day(6 + 1)] = self.counts[of_day(6 + 1)]
+        let reset = of_day(self.lifecycle.spawn_interval);
+        self.counts[reset] = self.counts[reset]
             .checked_add(self.counts[self.zero_index])
             .ok_or_else(|| anyhow!("Counter overflowed, need more bits!"))
             .context("Advancing a new generation")?;
@@ -80,6 +150,82 @@ impl School {
 
         Ok(())
     }
+
+    fn total(&self) -> Result<FishCount> {
+        // NB: .sum() isn't checked, so implement it via a fold
+        self.counts
+            .iter()
+            .try_fold(0u64, |acc, v| acc.checked_add(*v))
+            .ok_or_else(|| anyhow!("Counter overflowed, need more bits!"))
+    }
+
+    // Counts in timer order, undoing the rotation of the storage
+    fn timers(&self) -> impl Iterator<Item = FishCount> + '_ {
+        let timer_end = self.counts.len();
+        (0..timer_end).map(move |timer| self.counts[(self.zero_index + timer) % timer_end])
+    }
+}
+
+// Square matrix of arbitrary precision counts, [row][column]
+type Matrix = Vec<Vec<BigUint>>;
+
+fn identity(size: usize) -> Matrix {
+    (0..size)
+        .map(|row| {
+            (0..size)
+                .map(|col| BigUint::from(u8::from(row == col)))
+                .collect()
+        })
+        .collect()
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    (0..a.len())
+        .map(|row| {
+            (0..b[0].len())
+                .map(|col| (0..b.len()).map(|k| &a[row][k] * &b[k][col]).sum())
+                .collect()
+        })
+        .collect()
+}
+
+// One day's change, as a matrix applied to a column vector of counts in timer order:
+// every timer counts down by one, and the zeros go to both the reset timer (6) and the
+// newborn timer (8).
+fn transition(lifecycle: Lifecycle) -> Matrix {
+    let timer_end = lifecycle.timer_end();
+    let mut m = vec![vec![BigUint::default(); timer_end]; timer_end];
+    for timer in 1..timer_end {
+        m[timer - 1][timer] = BigUint::from(1u8);
+    }
+    // These are the same timer if there's no newborn delay
+    m[lifecycle.spawn_interval - 1][0] += 1u8;
+    m[timer_end - 1][0] += 1u8;
+    m
+}
+
+// Raise the transition matrix to the power of days by repeated squaring, so this takes
+// O(log days) matrix multiplications rather than advancing one day at a time.
+fn forecast(input: &str, lifecycle: Lifecycle, mut days: u64) -> Result<BigUint> {
+    let school = School::new(lifecycle, finish(separated_list1(tag(","), u8)(input))?)?;
+
+    let mut result = identity(lifecycle.timer_end());
+    let mut square = transition(lifecycle);
+    while days > 0 {
+        if days & 1 == 1 {
+            result = multiply(&result, &square);
+        }
+        days >>= 1;
+        if days > 0 {
+            square = multiply(&square, &square);
+        }
+    }
+
+    let counts: Vec<BigUint> = school.timers().map(BigUint::from).collect();
+    Ok(result
+        .iter()
+        .map(|row| row.iter().zip(&counts).map(|(m, c)| m * c).sum::<BigUint>())
+        .sum())
 }
 
 /*
@@ -110,20 +256,45 @@ dhat: The data has been saved to dhat-heap.json, and is viewable with dhat/dh_vi
 0.00user 0.00system 0:00.00elapsed 80%CPU (0avgtext+0avgdata 10464maxresident)k
 0inputs+16outputs (0major+828minor)pagefaults 0swaps
 
+Forecast with matrix exponentiation and BigUint (release, no dhat):
+`day06 forecast 10000`: 382 digits, instantly
+`day06 forecast 1000000`: 37,838 digits in 0.48s
+
 */
 
-fn process(input: &str) -> Result<u64> {
-    let mut school = School::new(finish(separated_list1(tag(","), u8)(input))?);
+fn process(input: &str, lifecycle: Lifecycle) -> Result<u64> {
+    let mut school = School::new(lifecycle, finish(separated_list1(tag(","), u8)(input))?)?;
     for _day in 0..256 {
         //dbg!(&school);
         school.advance()?;
     }
     dbg!(&school);
-    // NB: .sum() isn't checked, so implement it via a fold
-    school
-        .counts
-        .iter()
-        .try_fold(0u64, |acc, v| acc.checked_add(*v))
-        .ok_or_else(|| anyhow!("Counter overflowed, need more bits!"))
-        .context("Final sum")
+    school.total().context("Final sum")
+}
+
+// CSV of the population on each day from 0 to days, with the count for each timer value
+fn series(input: &str, lifecycle: Lifecycle, days: usize, out: &mut impl Write) -> Result<()> {
+    let mut school = School::new(lifecycle, finish(separated_list1(tag(","), u8)(input))?)?;
+
+    write!(out, "day,total")?;
+    for timer in 0..lifecycle.timer_end() {
+        write!(out, ",timer{timer}")?;
+    }
+    writeln!(out)?;
+
+    for day in 0..=days {
+        if day > 0 {
+            school.advance().with_context(|| format!("Day {day}"))?;
+        }
+        let total = school
+            .total()
+            .with_context(|| format!("Sum on day {day}"))?;
+        write!(out, "{day},{total}")?;
+        for count in school.timers() {
+            write!(out, ",{count}")?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
 }
@@ -4,7 +4,11 @@
 #![warn(clippy::nursery)]
 //#![warn(clippy::cargo)]
 
+use anyhow::{anyhow, bail, ensure, Context, Result};
 //use ndarray::prelude::*;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::time::Instant;
 
 #[cfg(feature = "dhat-heap")]
 #[global_allocator]
@@ -12,37 +16,294 @@ static ALLOC: dhat::Alloc = dhat::Alloc;
 
 type Coord = i64;
 
-fn cost(n: u64) -> u64 {
-    (1..=n).sum()
+#[derive(Debug, Clone)]
+enum FuelModel {
+    // One unit of fuel per step (part 1)
+    Linear,
+    // Each step costs one more than the last (part 2)
+    Triangular,
+    // The square of the distance
+    Quadratic,
+    // Cost of moving n steps is cumulative[n]; it's built from a table of the cost of each
+    // successive step, and steps past the end of that table cost the same as the last one
+    PerStep { cumulative: Vec<u64> },
+}
+
+impl FuelModel {
+    fn per_step(step_costs: &[u64]) -> Self {
+        let cumulative = std::iter::once(0)
+            .chain(step_costs.iter().scan(0, |total, step| {
+                *total += step;
+                Some(*total)
+            }))
+            .collect();
+        Self::PerStep { cumulative }
+    }
+
+    fn cost(&self, distance: u64) -> u64 {
+        match self {
+            Self::Linear => distance,
+            Self::Triangular => distance * (distance + 1) / 2,
+            Self::Quadratic => distance * distance,
+            Self::PerStep { cumulative } => {
+                let last = cumulative.len() - 1;
+                match usize::try_from(distance) {
+                    Ok(steps) if steps <= last => cumulative[steps],
+                    _ => {
+                        let last_step = cumulative[last] - cumulative[last.saturating_sub(1)];
+                        cumulative[last] + (distance - last as u64) * last_step
+                    }
+                }
+            }
+        }
+    }
+
+    fn total_cost(&self, nums: &[Coord], pos: Coord) -> u64 {
+        nums.iter()
+            .map(|n| self.cost((n - pos).unsigned_abs()))
+            .sum()
+    }
+
+    // Find all the positions with the least total cost, and that cost
+    fn optimise(&self, nums: &[Coord]) -> Optimum {
+        match self {
+            // The sum of distances is smallest at the median, or anywhere between the two
+            // middle values if there's an even number of crabs
+            Self::Linear => {
+                let mut sorted = nums.to_vec();
+                let mid = sorted.len() / 2;
+                let upper = *sorted.select_nth_unstable(mid).1;
+                let lower = if sorted.len().is_multiple_of(2) {
+                    *sorted[..mid].iter().max().unwrap()
+                } else {
+                    upper
+                };
+                // Every position in between costs the same, so there's no need to check them
+                Optimum {
+                    positions: vec![lower..=upper],
+                    cost: self.total_cost(nums, lower),
+                }
+            }
+            // The least quadratic cost is at the mean, and the least triangular cost is
+            // within 1/2 of it, so only the positions right around it need to be checked
+            Self::Triangular | Self::Quadratic => {
+                let count = Coord::try_from(nums.len()).unwrap();
+                let mean = nums.iter().sum::<Coord>().div_euclid(count);
+                Optimum::least((mean - 1..=mean + 2).map(|pos| (pos, self.total_cost(nums, pos))))
+            }
+            // No shortcuts for an arbitrary table
+            Self::PerStep { .. } => self.brute_force(nums),
+        }
+    }
+
+    // Check every position, with every crab
+    fn brute_force(&self, nums: &[Coord]) -> Optimum {
+        Optimum::least(self.cost_curve(nums))
+    }
+
+    // Total cost at every position between the outermost crabs
+    fn cost_curve<'a>(&'a self, nums: &'a [Coord]) -> impl Iterator<Item = (Coord, u64)> + 'a {
+        let min_coord = nums.iter().min().copied().unwrap_or_default();
+        let max_coord = nums.iter().max().copied().unwrap_or_default();
+        (min_coord..=max_coord).map(|pos| (pos, self.total_cost(nums, pos)))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Optimum {
+    // Every position tied for the least cost, in order, as runs of neighbouring positions
+    positions: Vec<RangeInclusive<Coord>>,
+    cost: u64,
 }
 
-fn main() {
+impl Optimum {
+    // Streaming minimum, so the costs don't need to be collected and sorted
+    fn least(costs: impl Iterator<Item = (Coord, u64)>) -> Self {
+        costs.fold(Self::default(), |mut best, (pos, cost)| {
+            if best.positions.is_empty() || cost < best.cost {
+                best.positions.clear();
+                best.positions.push(pos..=pos);
+                best.cost = cost;
+            } else if cost == best.cost {
+                match best.positions.last_mut() {
+                    Some(run) if *run.end() + 1 == pos => *run = *run.start()..=pos,
+                    _ => best.positions.push(pos..=pos),
+                }
+            }
+            best
+        })
+    }
+}
+
+impl std::fmt::Display for FuelModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Linear => "linear",
+            Self::Triangular => "triangular",
+            Self::Quadratic => "quadratic",
+            Self::PerStep { .. } => "per-step",
+        })
+    }
+}
+
+impl std::str::FromStr for FuelModel {
+    type Err = anyhow::Error;
+
+    // One of "linear", "triangular", "quadratic", or a list of step costs like "1,2,3"
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "triangular" => Ok(Self::Triangular),
+            "quadratic" => Ok(Self::Quadratic),
+            _ => {
+                let steps = s
+                    .split(',')
+                    .map(|v| v.trim().parse())
+                    .collect::<Result<Vec<u64>, _>>()
+                    .with_context(|| format!("Invalid fuel model {s:?}"))?;
+                Ok(Self::per_step(&steps))
+            }
+        }
+    }
+}
+
+fn main() -> Result<()> {
     #[cfg(feature = "dhat-heap")]
     let _profiler = dhat::Profiler::new_heap();
 
-    assert_eq!(cost(4), 10);
+    assert_eq!(FuelModel::Triangular.cost(4), 10);
+    assert_eq!(FuelModel::per_step(&[1, 2, 3]).cost(4), 9);
 
-    let nums = include_str!("../../inputs/day07.txt")
-        .trim()
-        .split(',')
-        .map(|v| v.parse().unwrap())
+    let mut input = "inputs/day07.txt".to_owned();
+    let mut model = None;
+    let mut curve = None;
+    let mut bench_only = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "bench" => bench_only = true,
+            "--input" => {
+                input = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --input"))?;
+            }
+            "--model" => {
+                model = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow!("Missing value for --model"))?
+                        .parse::<FuelModel>()?,
+                );
+            }
+            "--curve" => {
+                curve = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow!("Missing value for --curve"))?,
+                );
+            }
+            _ => bail!(
+                "Unknown argument {arg:?}, expected `bench`, `--input FILE`, \
+                `--model linear|triangular|quadratic|STEP,STEP,...` or `--curve FILE`"
+            ),
+        }
+    }
+
+    let nums = parse(&std::fs::read_to_string(&input).with_context(|| format!("Reading {input}"))?)
+        .with_context(|| format!("Parsing {input}"))?;
+
+    if bench_only {
+        bench(&nums);
+        return Ok(());
+    }
+
+    let models = model.map_or_else(
+        || vec![FuelModel::Linear, FuelModel::Triangular],
+        |model| vec![model],
+    );
+    if let Some(path) = curve {
+        let mut out = std::io::BufWriter::new(
+            std::fs::File::create(&path).with_context(|| format!("Creating {path}"))?,
+        );
+        write_curves(&models, &nums, &mut out)?;
+        out.flush()?;
+    }
+
+    let answer = models
+        .iter()
+        .map(|model| model.optimise(&nums))
         .collect::<Vec<_>>();
+    println!("Answer: {answer:?}");
 
-    let max_coord = *nums.iter().max().unwrap();
-    let mut cost_per_position = (0..=max_coord)
-        .map(|pos| {
-            (
-                pos,
-                nums.iter()
-                    .map(|n| cost((n - pos as Coord).unsigned_abs()))
-                    .sum::<u64>(),
-            )
-        })
+    Ok(())
+}
+
+// CSV of the total cost at each position, with a column for each model
+fn write_curves(models: &[FuelModel], nums: &[Coord], out: &mut impl Write) -> Result<()> {
+    write!(out, "position")?;
+    for model in models {
+        write!(out, ",{model}")?;
+    }
+    writeln!(out)?;
+
+    let mut curves = models
+        .iter()
+        .map(|model| model.cost_curve(nums))
         .collect::<Vec<_>>();
+    // Every curve covers the same positions, so they can be read in step
+    while let Some((pos, cost)) = curves[0].next() {
+        write!(out, "{pos},{cost}")?;
+        for curve in &mut curves[1..] {
+            let (_, cost) = curve.next().context("Cost curves of different lengths")?;
+            write!(out, ",{cost}")?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
 
-    cost_per_position.sort_by(|a, b| a.1.cmp(&b.1));
-    //dbg!(&cost_per_position);
-    println!("Answer: {:?}", cost_per_position[0]);
+fn parse(input: &str) -> Result<Vec<Coord>> {
+    let nums = input
+        .trim()
+        .split(',')
+        .map(|v| {
+            v.parse()
+                .with_context(|| format!("Invalid crab position {v:?}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(!nums.is_empty(), "No crabs!");
+    Ok(nums)
+}
+
+// The original part 2 cost, adding up each step in a loop, kept for the benchmark
+fn loop_cost(n: u64) -> u64 {
+    (1..=n).sum()
+}
+
+fn bench(nums: &[Coord]) {
+    fn time<T: std::fmt::Debug>(name: &str, f: impl Fn() -> T) {
+        let start = Instant::now();
+        let answer = f();
+        println!("{name:>24}: {answer:?} in {:?}", start.elapsed());
+    }
+
+    time("part 1, median", || FuelModel::Linear.optimise(nums));
+    time("part 1, brute force", || {
+        FuelModel::Linear.brute_force(nums)
+    });
+    time("part 2, mean", || FuelModel::Triangular.optimise(nums));
+    time("part 2, brute force", || {
+        FuelModel::Triangular.brute_force(nums)
+    });
+    time("part 2, brute force loop", || {
+        let min_coord = nums.iter().min().copied().unwrap_or_default();
+        let max_coord = nums.iter().max().copied().unwrap_or_default();
+        Optimum::least((min_coord..=max_coord).map(|pos| {
+            let total = nums
+                .iter()
+                .map(|n| loop_cost((n - pos).unsigned_abs()))
+                .sum::<u64>();
+            (pos, total)
+        }))
+    });
 }
 
 /*
@@ -54,4 +315,12 @@ dhat: At t-end:  1,024 bytes in 1 blocks
 dhat: The data has been saved to dhat-heap.json, and is viewable with dhat/dh_view.html
 0.00user 0.00system 0:00.00elapsed 100%CPU (0avgtext+0avgdata 9868maxresident)k
 0inputs+16outputs (0major+788minor)pagefaults 0swaps
+
+Median and mean instead of brute force (`day07 bench`, release build):
+Answer: ((371, 341558), (484, 93214037))
+          part 1, median: (371, 341558) in 9.132µs
+     part 1, brute force: (371, 341558) in 919.991µs
+            part 2, mean: (484, 93214037) in 3.315µs
+     part 2, brute force: (484, 93214037) in 2.903832ms
+part 2, brute force loop: (484, 93214037) in 4.265586ms
 */
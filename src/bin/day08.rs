@@ -4,13 +4,13 @@
 #![warn(clippy::nursery)]
 //#![warn(clippy::cargo)]
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use bitvec::prelude::*;
 //use ndarray::prelude::*;
 use nom::{
     bytes::complete::tag,
     character::complete::{alpha1, line_ending, space0, space1},
-    combinator::{all_consuming, map},
+    combinator::{all_consuming, map, map_res},
     multi::{many1, separated_list1},
     sequence::{delimited, preceded, separated_pair, terminated},
     IResult,
@@ -24,9 +24,38 @@ fn main() -> Result<()> {
     #[cfg(feature = "dhat-heap")]
     let _profiler = dhat::Profiler::new_heap();
 
+    let mut alphabet = None;
+    let mut verbose = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--glyphs" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing file for --glyphs"))?;
+                alphabet = Some(
+                    std::fs::read_to_string(&path)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|defs| defs.parse())
+                        .with_context(|| format!("Loading glyphs from {path}"))?,
+                );
+            }
+            "--verbose" => verbose = true,
+            _ => bail!("Unknown argument {arg:?}, expected `--glyphs FILE` or `--verbose`"),
+        }
+    }
+    let alphabet = match alphabet {
+        Some(alphabet) => alphabet,
+        None => SEVEN_SEGMENT_DIGITS.parse()?,
+    };
+
     println!(
         "Answer: {:?}",
-        process(&std::fs::read_to_string("inputs/day08.txt")?)?
+        process(
+            &std::fs::read_to_string("inputs/day08.txt")?,
+            &alphabet,
+            verbose
+        )?
     );
 
     Ok(())
@@ -39,124 +68,402 @@ fn finish<A>(parsed: IResult<&str, A>) -> Result<A> {
         .map(|(_input, value)| value)
 }
 
-type Segments = BitArr!(for 7, in u8, Msb0);
+// Glyph definitions are the names of the segments, and then each glyph and the segments
+// lit to show it. The glyphs are listed in order of their value. Wires in the input are
+// named the same as the segments. This is the standard seven-segment display:
+//  aaaa
+// b    c
+// b    c
+//  dddd
+// e    f
+// e    f
+//  gggg
+const SEVEN_SEGMENT_DIGITS: &str = "\
+segments abcdefg
+0 abcefg
+1 cf
+2 acdeg
+3 acdfg
+4 bcdf
+5 abdfg
+6 abdefg
+7 acf
+8 abcdefg
+9 abcdfg
+";
+
+// Enough for a sixteen-segment display
+const MAX_SEGMENTS: usize = 16;
+
+// Bit i is the wire (or segment) named by Alphabet.names[i]
+type Segments = BitArr!(for MAX_SEGMENTS, in u16, Msb0);
+
+#[derive(Debug)]
+struct Alphabet {
+    names: Vec<char>,
+    glyphs: Vec<char>,
+    shapes: Vec<Segments>,
+}
+
+impl Alphabet {
+    fn all_segments(&self) -> Segments {
+        let mut all = Segments::ZERO;
+        all[..self.names.len()].fill(true);
+        all
+    }
+
+    fn segments(&self, names: &str) -> Result<Segments> {
+        let mut segments = Segments::ZERO;
+        for c in names.chars() {
+            let segment = self
+                .names
+                .iter()
+                .position(|&name| name == c)
+                .ok_or_else(|| anyhow!("Unknown segment {c:?} in {names:?}"))?;
+            segments.set(segment, true);
+        }
+        Ok(segments)
+    }
+
+    fn segment_names(&self, segments: Segments) -> String {
+        segments.iter_ones().map(|i| self.names[i]).collect()
+    }
+
+    // Glyphs that can be recognized just by how many segments they light up
+    fn has_unique_count(&self, segments: Segments) -> bool {
+        self.shapes
+            .iter()
+            .filter(|shape| shape.count_ones() == segments.count_ones())
+            .count()
+            == 1
+    }
+
+    // Value of a sequence of glyphs, treating each as a digit in base glyphs.len()
+    fn value(&self, glyphs: &[usize]) -> Result<u64> {
+        let base = u64::try_from(self.glyphs.len())?;
+        glyphs.iter().try_fold(0, |acc: u64, &glyph| {
+            acc.checked_mul(base)
+                .and_then(|acc| acc.checked_add(glyph as u64))
+                .ok_or_else(|| anyhow!("Display value is too large"))
+        })
+    }
+}
+
+impl std::str::FromStr for Alphabet {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        let names: Vec<char> = lines
+            .next()
+            .and_then(|(_, line)| line.strip_prefix("segments "))
+            .ok_or_else(|| anyhow!("Glyph definitions must start with `segments NAMES`"))?
+            .trim()
+            .chars()
+            .collect();
+        ensure!(
+            (1..=MAX_SEGMENTS).contains(&names.len()),
+            "There must be from 1 to {MAX_SEGMENTS} segments"
+        );
+        for (i, name) in names.iter().enumerate() {
+            ensure!(name.is_ascii_lowercase(), "Segment name {name:?} isn't a-z");
+            ensure!(
+                !names[..i].contains(name),
+                "Duplicate segment name {name:?}"
+            );
+        }
+
+        let mut alphabet = Self {
+            names,
+            glyphs: vec![],
+            shapes: vec![],
+        };
+        for (line_num, line) in lines {
+            let (glyph, shape) = line
+                .split_once(char::is_whitespace)
+                .and_then(|(glyph, shape)| {
+                    let mut chars = glyph.chars();
+                    chars
+                        .next()
+                        .filter(|_| chars.next().is_none())
+                        .map(|glyph| (glyph, shape.trim()))
+                })
+                .ok_or_else(|| anyhow!("Line {line_num}: expected a glyph and its segments"))?;
+            let shape = alphabet
+                .segments(shape)
+                .with_context(|| format!("Line {line_num}"))?;
+            ensure!(
+                !alphabet.glyphs.contains(&glyph) && !alphabet.shapes.contains(&shape),
+                "Line {line_num}: glyph {glyph:?} or its segments are a duplicate"
+            );
+            alphabet.glyphs.push(glyph);
+            alphabet.shapes.push(shape);
+        }
+        ensure!(alphabet.glyphs.len() > 1, "There must be at least 2 glyphs");
+
+        Ok(alphabet)
+    }
+}
+
 #[derive(Debug)]
 struct Entry {
     patterns: Vec<Segments>,
     display: Vec<Segments>,
 
+    // The wires that show each glyph
     digits: Vec<Segments>,
+    // The segment that each wire is connected to
     wires: Vec<Segments>,
 }
 
 impl Entry {
-    fn new(patterns: Vec<Segments>, display: Vec<Segments>) -> Self {
-        let digits = vec![Segments::default(); 10];
-        let wires = vec![Segments::default(); 7];
-
+    const fn new(patterns: Vec<Segments>, display: Vec<Segments>) -> Self {
         Self {
             patterns,
             display,
-            digits,
-            wires,
+            digits: vec![],
+            wires: vec![],
         }
     }
 
-    // Remove and return the pattern (assuming exactly one match) that:
-    // - has num_segments bits set, and
-    // - satisfies predicate(self, pattern)
-    fn grab_pattern(
-        &mut self,
-        num_segments: usize,
-        predicate: fn(&Self, &Segments) -> bool,
-    ) -> Segments {
-        let pos: usize = self
-            .patterns
+    // Find the one mapping of wires to segments that makes every pattern and displayed
+    // value a valid glyph, then decode the display with it into glyph indexes
+    fn solve(&mut self, alphabet: &Alphabet) -> Result<Vec<usize>> {
+        let observed: Vec<Segments> = self.patterns.iter().chain(&self.display).copied().collect();
+
+        let mut candidates = vec![alphabet.all_segments(); alphabet.names.len()];
+        propagate(alphabet, &observed, &mut candidates)?;
+
+        let mut solutions = vec![];
+        search(
+            alphabet,
+            &observed,
+            &candidates,
+            &mut vec![None; alphabet.names.len()],
+            Segments::ZERO,
+            &mut solutions,
+        );
+        let mapping = match &solutions[..] {
+            [] => bail!("Inconsistent patterns, no wiring can display them all as glyphs"),
+            [mapping] => mapping,
+            _ => bail!("Ambiguous patterns, more than one wiring can display them as glyphs"),
+        };
+
+        self.wires = mapping
+            .iter()
+            .map(|&segment| {
+                let mut wire = Segments::ZERO;
+                wire.set(segment, true);
+                wire
+            })
+            .collect();
+        self.digits = alphabet
+            .shapes
+            .iter()
+            .map(|shape| {
+                let mut wires = Segments::ZERO;
+                for (wire, &segment) in mapping.iter().enumerate() {
+                    wires.set(wire, shape[segment]);
+                }
+                wires
+            })
+            .collect();
+
+        self.display
             .iter()
-            .position(|pat| pat.count_ones() == num_segments && predicate(self, pat))
-            .unwrap();
-        self.patterns.swap_remove(pos)
-    }
-
-    fn solve(&mut self) -> u32 {
-        // First, the easy ones: each of these digits has a unique number of segments on
-        self.digits[1] = self.grab_pattern(2, |_, _| true);
-        self.digits[7] = self.grab_pattern(3, |_, _| true);
-        self.digits[4] = self.grab_pattern(4, |_, _| true);
-        self.digits[8] = self.grab_pattern(7, |_, _| true);
-
-        // 1 & 6 overlap by 1, whereas 1 & 9 and 1 & 0 overlap by 2
-        self.digits[6] =
-            self.grab_pattern(6, |self_, pat| (*pat & self_.digits[1]).count_ones() == 1);
-
-        // Segment 'b' is known from 8 - 6
-        self.wires[1] = self.digits[8] & !self.digits[6];
-
-        // 5 is the only 5-segment number with segment 'b' off
-        self.digits[5] =
-            self.grab_pattern(5, |self_, pat| (*pat & self_.wires[1]).count_ones() == 0);
-
-        // Segment 'f' is known from 1 - b
-        self.wires[5] = self.digits[1] & !self.wires[1];
-
-        // 2 is the only 5-segment number with segment 'f' off
-        self.digits[2] =
-            self.grab_pattern(5, |self_, pat| (*pat & self_.wires[5]).count_ones() == 0);
-
-        // 9 is the only 6-segment number that completely covers 4
-        self.digits[9] = self.grab_pattern(6, |self_, pat| {
-            let d4 = self_.digits[4];
-            (d4 & *pat).count_ones() == d4.count_ones()
-        });
-
-        // 3 is the only 5-segment number remaining
-        self.digits[3] = self.grab_pattern(5, |_, _| true);
-
-        // 0 is the only 6-segment number remaining
-        self.digits[0] = self.grab_pattern(6, |_, _| true);
-
-        let dlen = self.display.len() as u32;
-
-        (0..dlen)
-            .map(|i| {
-                10_u32.pow(dlen - 1 - i)
-                    * self
-                        .digits
-                        .iter()
-                        .position(|&d| d == self.display[i as usize])
-                        .unwrap() as u32
+            .map(|pattern| {
+                self.digits
+                    .iter()
+                    .position(|d| d == pattern)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Display pattern {} is not a glyph",
+                            alphabet.segment_names(*pattern)
+                        )
+                    })
             })
-            .sum::<u32>()
+            .collect()
+    }
+
+    // The entry's patterns, with the wires of each in order
+    fn input(&self, alphabet: &Alphabet) -> String {
+        let names = |patterns: &[Segments]| {
+            patterns
+                .iter()
+                .map(|&p| alphabet.segment_names(p))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        format!("{} | {}", names(&self.patterns), names(&self.display))
+    }
+
+    // Details of a solved entry, to help track down a mis-decode
+    fn report(&self, alphabet: &Alphabet, glyphs: &[usize]) -> Result<String> {
+        use std::fmt::Write;
+
+        let mut out = String::new();
+        writeln!(out, "  input:   {}", self.input(alphabet))?;
+        writeln!(
+            out,
+            "  display: {}",
+            glyphs
+                .iter()
+                .map(|&g| alphabet.glyphs[g])
+                .collect::<String>()
+        )?;
+        write!(out, "  glyphs: ")?;
+        for (glyph, wires) in alphabet.glyphs.iter().zip(&self.digits) {
+            write!(out, " {glyph}={}", alphabet.segment_names(*wires))?;
+        }
+        write!(out, "\n  wires:  ")?;
+        for (wire, segment) in alphabet.names.iter().zip(&self.wires) {
+            write!(out, " {wire}->{}", alphabet.segment_names(*segment))?;
+        }
+        writeln!(out)?;
+        Ok(out)
     }
 }
 
-fn segments(input: &str) -> IResult<&str, Segments> {
-    map(alpha1, |s: &str| {
-        let data = s
-            .chars()
-            .map(|c| u8::try_from(c).unwrap() - b'a')
-            .fold(0u8, |acc, v| acc | 1 << v);
-        Segments::new([data; 1])
-    })(input)
+// Narrow down the segments each wire could be connected to. A pattern's wires can only
+// go to segments used by some glyph with the same number of segments, and its unlit
+// wires can't go to segments that all of those glyphs use.
+fn propagate(
+    alphabet: &Alphabet,
+    observed: &[Segments],
+    candidates: &mut [Segments],
+) -> Result<()> {
+    loop {
+        let before = candidates.to_vec();
+
+        for pattern in observed {
+            let (union, intersection) = alphabet
+                .shapes
+                .iter()
+                .filter(|shape| shape.count_ones() == pattern.count_ones())
+                .fold(
+                    (Segments::ZERO, alphabet.all_segments()),
+                    |(union, intersection), shape| (union | *shape, intersection & *shape),
+                );
+            ensure!(
+                union.any(),
+                "No glyph has {} segments: {}",
+                pattern.count_ones(),
+                alphabet.segment_names(*pattern)
+            );
+
+            for (wire, possible) in candidates.iter_mut().enumerate() {
+                if pattern[wire] {
+                    *possible &= union;
+                } else {
+                    *possible &= !intersection;
+                }
+            }
+        }
+
+        // A wire with only one possible segment doesn't share it with any other wire
+        for wire in 0..candidates.len() {
+            if candidates[wire].count_ones() == 1 {
+                let segment = candidates[wire];
+                for (other, possible) in candidates.iter_mut().enumerate() {
+                    if other != wire {
+                        *possible &= !segment;
+                    }
+                }
+            }
+        }
+
+        if let Some(wire) = candidates.iter().position(|possible| possible.not_any()) {
+            bail!(
+                "Inconsistent patterns, wire {} can't go to any segment",
+                alphabet.names[wire]
+            );
+        }
+        if candidates == before {
+            return Ok(());
+        }
+    }
+}
+
+// Depth-first search for complete wirings, trying the most constrained wire first. Stops
+// early once a second solution shows that the patterns are ambiguous.
+fn search(
+    alphabet: &Alphabet,
+    observed: &[Segments],
+    candidates: &[Segments],
+    mapping: &mut [Option<usize>],
+    used: Segments,
+    solutions: &mut Vec<Vec<usize>>,
+) {
+    if solutions.len() > 1 || !observed.iter().all(|&p| fits(alphabet, p, mapping)) {
+        return;
+    }
+
+    let next = (0..mapping.len())
+        .filter(|&wire| mapping[wire].is_none())
+        .min_by_key(|&wire| (candidates[wire] & !used).count_ones());
+    match next {
+        None => solutions.push(mapping.iter().flatten().copied().collect()),
+        Some(wire) => {
+            for segment in (candidates[wire] & !used).iter_ones() {
+                mapping[wire] = Some(segment);
+                let mut used = used;
+                used.set(segment, true);
+                search(alphabet, observed, candidates, mapping, used, solutions);
+            }
+            mapping[wire] = None;
+        }
+    }
 }
 
-fn entry(input: &str) -> IResult<&str, Entry> {
-    map(
-        terminated(
-            separated_pair(
-                separated_list1(space1, segments),
-                delimited(space0, tag("|"), space0),
-                separated_list1(space1, segments),
+// Could some glyph still be shown by this pattern, given the wires mapped so far?
+fn fits(alphabet: &Alphabet, pattern: Segments, mapping: &[Option<usize>]) -> bool {
+    let mut lit = Segments::ZERO;
+    let mut unlit = Segments::ZERO;
+    for (wire, segment) in mapping.iter().enumerate() {
+        if let Some(segment) = *segment {
+            if pattern[wire] {
+                lit.set(segment, true);
+            } else {
+                unlit.set(segment, true);
+            }
+        }
+    }
+
+    alphabet.shapes.iter().any(|shape| {
+        shape.count_ones() == pattern.count_ones()
+            && (lit & !*shape).not_any()
+            && (unlit & *shape).not_any()
+    })
+}
+
+fn segments(alphabet: &Alphabet) -> impl FnMut(&str) -> IResult<&str, Segments> + '_ {
+    move |input| map_res(alpha1, |s: &str| alphabet.segments(s))(input)
+}
+
+fn entry(alphabet: &Alphabet) -> impl FnMut(&str) -> IResult<&str, Entry> + '_ {
+    move |input| {
+        map(
+            terminated(
+                separated_pair(
+                    separated_list1(space1, segments(alphabet)),
+                    delimited(space0, tag("|"), space0),
+                    separated_list1(space1, segments(alphabet)),
+                ),
+                preceded(space0, line_ending),
             ),
-            preceded(space0, line_ending),
-        ),
-        |(patterns, display)| Entry::new(patterns, display),
-    )(input)
+            |(patterns, display)| Entry::new(patterns, display),
+        )(input)
+    }
 }
 
-fn process(input: &str) -> Result<(usize, u64)> {
+fn process(input: &str, alphabet: &Alphabet, verbose: bool) -> Result<(usize, u64)> {
     let mut entries: Vec<Entry> =
-        finish(all_consuming(many1(entry))(input)).context("parsing entries")?;
+        finish(all_consuming(many1(entry(alphabet)))(input)).context("parsing entries")?;
     //dbg!(&entries);
 
     // Part 1
@@ -165,16 +472,26 @@ fn process(input: &str) -> Result<(usize, u64)> {
         .map(|e| {
             e.display
                 .iter()
-                .map(|d| d.count_ones())
-                .filter(|len| matches!(len, 2 | 3 | 4 | 7))
+                .filter(|&&d| alphabet.has_unique_count(d))
                 .count()
         })
         .sum();
 
     let part2 = entries
         .iter_mut()
-        .map(|e| u64::from(e.solve()))
-        .sum::<u64>();
+        .enumerate()
+        .map(|(i, e)| {
+            let glyphs = e
+                .solve(alphabet)
+                .with_context(|| format!("Solving entry {}: {}", i + 1, e.input(alphabet)))?;
+            let value = alphabet.value(&glyphs)?;
+            if verbose {
+                println!("Entry {}: {value}", i + 1);
+                print!("{}", e.report(alphabet, &glyphs)?);
+            }
+            Ok(value)
+        })
+        .sum::<Result<u64>>()?;
     //dbg!(&entries.first().unwrap().digits);
     Ok((part1, part2))
 }
@@ -4,9 +4,8 @@
 #![warn(clippy::nursery)]
 //#![warn(clippy::cargo)]
 
-use anyhow::{anyhow, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use itertools::Itertools;
-use std::collections::BTreeSet;
 
 #[cfg(feature = "dhat-heap")]
 #[global_allocator]
@@ -16,72 +15,266 @@ fn main() -> Result<()> {
     #[cfg(feature = "dhat-heap")]
     let _profiler = dhat::Profiler::new_heap();
 
-    println!(
-        "Answer: {:?}",
-        process(&std::fs::read("inputs/day09.txt")?)?
-    );
+    let input = std::fs::read("inputs/day09.txt")?;
+    let mut decoded = false;
+    let mut command = vec![];
+    for arg in std::env::args().skip(1) {
+        if arg == "--decoded" {
+            decoded = true;
+        } else {
+            command.push(arg);
+        }
+    }
+
+    let hm = Heightmap::new(&input)?;
+    if decoded {
+        run(&hm.decode(), &command)
+    } else {
+        run(&hm, &command)
+    }
+}
+
+fn run(hm: &impl Heights, command: &[String]) -> Result<()> {
+    match command.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => println!("Answer: {:?}", process(hm)),
+        ["ansi"] => print!("{}", Picture::new(hm).ansi()),
+        ["ppm", ref path @ ..] if path.len() <= 1 => {
+            let path = path.first().unwrap_or(&"day09.ppm");
+            Picture::new(hm)
+                .write_ppm(path)
+                .with_context(|| format!("Writing {path}"))?;
+        }
+        _ => bail!(
+            "Unknown command {command:?}, expected `ansi` or `ppm [FILE]`, \
+            and optionally `--decoded`"
+        ),
+    }
 
     Ok(())
 }
 
-fn process(input: &[u8]) -> Result<(i32, i32)> {
-    let hm = Heightmap::new(input)?;
-    let low_points = (0..hm.width)
-        .cartesian_product(0..hm.height)
+fn process(hm: &impl Heights) -> (i32, usize) {
+    let low_points = low_points(hm);
+
+    let low_heights = low_points.iter().map(|&p| hm.at(p)).collect::<Vec<_>>();
+    //dbg!(&low_points, &low_heights,);
+    let part1 = low_heights.iter().map(|v| v + 1).sum::<i32>();
+
+    let mut sizes = Basins::new(hm).sizes();
+    sizes.sort_unstable();
+    let part2 = sizes.iter().rev().take(3).product::<usize>();
+
+    (part1, part2)
+}
+
+fn low_points(hm: &impl Heights) -> Vec<Location> {
+    (0..hm.width())
+        .cartesian_product(0..hm.height())
         .filter(|&loc| {
-            let lowest_neighbor = Heightmap::neighbors(loc)
-                .map(|nloc| hm.at(nloc))
-                .min()
-                .unwrap();
+            let lowest_neighbor = neighbors(loc).map(|nloc| hm.at(nloc)).min().unwrap();
             hm.at(loc) < lowest_neighbor
         })
-        .collect::<Vec<Location>>();
+        .collect()
+}
 
-    let low_heights = low_points.iter().map(|&p| hm.at(p)).collect::<Vec<_>>();
-    //dbg!(&low_points, &low_heights,);
-    let part1 = low_heights.iter().map(|v| *v as i32 + 1).sum::<i32>();
-
-    let mut part2 = low_points
-        .iter()
-        .map(|loc| basin_from_low_point(&hm, *loc).len() as i32)
-        .collect::<Vec<_>>();
-    part2.sort();
-    let part2 = part2.iter().rev().take(3).product::<i32>();
-
-    Ok((part1, part2))
-}
-
-fn basin_from_low_point(hm: &Heightmap, low_point: Location) -> BTreeSet<Location> {
-    fn advance(
-        hm: &Heightmap,
-        basin: &mut BTreeSet<Location>,
-        points: Vec<Location>,
-    ) -> Vec<Location> {
-        let mut new_points = Vec::<Location>::new();
-        for loc in points {
-            for n in Heightmap::neighbors(loc) {
-                if hm.at(n) < 9 && !basin.contains(&n) {
-                    basin.insert(n);
-                    new_points.push(n);
+// Every cell that isn't a 9 belongs to exactly one basin, so label them all at once as the
+// connected components of the map. This doesn't depend on finding a low point in each
+// basin, so it also handles basins with a flat bottom.
+#[derive(Debug)]
+struct Basins {
+    // Basin number of each cell, indexed like the heightmap storage; None for the 9s
+    // between basins and anything else in the storage
+    labels: Vec<Option<usize>>,
+    count: usize,
+}
+
+impl Basins {
+    fn new(hm: &impl Heights) -> Self {
+        let in_basin = |loc| hm.offset(loc).filter(|_| hm.at(loc) < 9);
+
+        // Union-find, joining each cell to its neighbors to the right and below
+        let mut parent: Vec<usize> = (0..hm.storage_len()).collect();
+        for loc @ (x, y) in hm.locations() {
+            if let Some(i) = in_basin(loc) {
+                for n in [in_basin((x + 1, y)), in_basin((x, y + 1))]
+                    .into_iter()
+                    .flatten()
+                {
+                    let a = find(&mut parent, i);
+                    let b = find(&mut parent, n);
+                    parent[a] = b;
                 }
             }
         }
-        new_points
-    }
-    let mut basin = BTreeSet::<Location>::new();
-    let mut new_points = vec![low_point];
-    loop {
-        new_points = advance(hm, &mut basin, new_points);
-        if new_points.is_empty() {
-            break;
+
+        // Number the basins in order of first appearance
+        let mut labels = vec![None; parent.len()];
+        let mut label_of_root = vec![None; parent.len()];
+        let mut count = 0;
+        for i in hm.locations().filter_map(in_basin) {
+            let root = find(&mut parent, i);
+            labels[i] = Some(*label_of_root[root].get_or_insert_with(|| {
+                count += 1;
+                count - 1
+            }));
+        }
+
+        Self { labels, count }
+    }
+
+    // Number of cells in each basin
+    fn sizes(&self) -> Vec<usize> {
+        let mut sizes = vec![0; self.count];
+        for &label in self.labels.iter().flatten() {
+            sizes[label] += 1;
+        }
+        sizes
+    }
+}
+
+type Colour = [u8; 3];
+
+// Colour of each cell: the three largest basins stand out in red, green and blue, and the
+// rest get muted colours that vary from one basin to the next. Low points are white, and
+// the 9s between basins are black.
+struct Picture<'a, H: Heights> {
+    hm: &'a H,
+    colours: Vec<Colour>,
+    low_points: Vec<bool>,
+}
+
+impl<'a, H: Heights> Picture<'a, H> {
+    const LARGEST: [Colour; 3] = [[220, 40, 40], [40, 200, 40], [50, 80, 230]];
+    const LOW_POINT: Colour = [255, 255, 255];
+    const RIDGE: Colour = [0, 0, 0];
+
+    fn new(hm: &'a H) -> Self {
+        let basins = Basins::new(hm);
+        let sizes = basins.sizes();
+        let mut by_size = (0..sizes.len()).collect::<Vec<_>>();
+        by_size.sort_unstable_by_key(|&label| std::cmp::Reverse(sizes[label]));
+        let mut rank = vec![0; sizes.len()];
+        for (r, &label) in by_size.iter().enumerate() {
+            rank[label] = r;
+        }
+
+        let muted = |n: usize| 80 + u8::try_from(n % 97).unwrap();
+        let basin_colours = (0..sizes.len())
+            .map(|label| match rank[label] {
+                r if r < Self::LARGEST.len() => Self::LARGEST[r],
+                _ => [
+                    muted(label * 37),
+                    muted(label * 59 + 13),
+                    muted(label * 83 + 29),
+                ],
+            })
+            .collect::<Vec<_>>();
+
+        let mut low_points = vec![false; hm.storage_len()];
+        for loc in self::low_points(hm) {
+            low_points[hm.offset(loc).unwrap()] = true;
+        }
+
+        let colours = basins
+            .labels
+            .iter()
+            .map(|label| label.map_or(Self::RIDGE, |label| basin_colours[label]))
+            .collect();
+
+        Self {
+            hm,
+            colours,
+            low_points,
+        }
+    }
+
+    fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = (Location, usize)> + '_> + '_ {
+        (0..self.hm.height()).map(move |y| {
+            (0..self.hm.width()).filter_map(move |x| self.hm.offset((x, y)).map(|i| ((x, y), i)))
+        })
+    }
+
+    // Each height on its basin's colour, with the low points in bold
+    fn ansi(&self) -> String {
+        use std::fmt::Write;
+
+        let mut out = String::new();
+        for row in self.rows() {
+            for (loc, i) in row {
+                let [r, g, b] = self.colours[i];
+                let style = if self.low_points[i] {
+                    "1;97"
+                } else if self.colours[i] == Self::RIDGE {
+                    "90"
+                } else {
+                    "30"
+                };
+                write!(out, "\x1b[{style};48;2;{r};{g};{b}m{}", self.hm.at(loc)).unwrap();
+            }
+            out.push_str("\x1b[0m\n");
+        }
+        out
+    }
+
+    // Binary colour image, one pixel per cell
+    fn write_ppm(&self, path: &str) -> Result<()> {
+        use std::io::Write;
+
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        write!(out, "P6\n{} {}\n255\n", self.hm.width(), self.hm.height())?;
+        for row in self.rows() {
+            for (_loc, i) in row {
+                let colour = if self.low_points[i] {
+                    Self::LOW_POINT
+                } else {
+                    self.colours[i]
+                };
+                out.write_all(&colour)?;
+            }
         }
+        out.flush()?;
+
+        Ok(())
     }
-    //dbg!(&low_point, &basin);
-    basin
+}
+
+// Find the root of i's set, pointing everything on the way at its grandparent
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
 }
 
 type Axis = i32;
 type Location = (i32, i32);
+
+// Looking up heights doesn't depend on how they're stored
+trait Heights {
+    fn width(&self) -> Axis;
+    fn height(&self) -> Axis;
+    // Length of the storage, so other data about each cell can be kept in a parallel Vec
+    fn storage_len(&self) -> usize;
+    // Position of a location in the storage, if it's within the grid
+    fn offset(&self, loc: Location) -> Option<usize>;
+    // Height at a location, or the top height (9) outside of the grid
+    fn at(&self, loc: Location) -> i32;
+
+    fn locations(&self) -> impl Iterator<Item = Location> + '_ {
+        (0..self.height()).flat_map(|y| (0..self.width()).map(move |x| (x, y)))
+    }
+}
+
+// Takes a point, and iterates over the 4 *points* (not their values!) surrounding it.
+// It *does* return coordinates for entries outside of the grid, because `at()` handles
+// them properly
+fn neighbors((x, y): Location) -> impl Iterator<Item = Location> {
+    [(1, 0), (0, 1), (-1, 0), (0, -1)]
+        .into_iter()
+        .map(move |(dx, dy)| (x + dx, y + dy))
+}
+
 struct Heightmap<'a> {
     width: Axis,
     height: Axis,
@@ -94,16 +287,17 @@ impl<'a> Heightmap<'a> {
         let width = input
             .iter()
             .position(|&b| b == b'\n')
-            .ok_or_else(|| anyhow!("No line ending in input!"))? as Axis;
+            .ok_or_else(|| anyhow!("No line ending in input!"))?;
+        let height = input.len() / (width + 1);
 
         let heightmap = Self {
             data: input,
-            width,
-            height: input.len() as Axis / (width + 1),
+            width: Axis::try_from(width)?,
+            height: Axis::try_from(height)?,
         };
 
         ensure!(
-            heightmap.data.len() as Axis == (heightmap.width + 1) * heightmap.height,
+            heightmap.data.len() == (width + 1) * height,
             "Impossible combo of (width, len): {:?}",
             (heightmap, input.len())
         );
@@ -111,29 +305,95 @@ impl<'a> Heightmap<'a> {
         Ok(heightmap)
     }
 
-    fn at(&self, (x, y): Location) -> i32 {
-        if (0..self.width as Axis).contains(&x) && (0..self.height as Axis).contains(&y) {
-            // Valid coordinates, look up data
-            let index = ((self.width as Axis + 1) * y + x) as usize;
-            // The bytes are all ascii digits
-            i32::from(self.data[index].saturating_sub(b'0'))
+    // Convert all the ASCII digits up front, to get a heightmap that can be indexed
+    fn decode(&self) -> DecodedHeightmap {
+        DecodedHeightmap {
+            width: self.width,
+            height: self.height,
+            data: self
+                .locations()
+                .map(|loc| self.data[self.offset(loc).unwrap()].saturating_sub(b'0'))
+                .collect(),
+        }
+    }
+}
+
+impl Heights for Heightmap<'_> {
+    fn width(&self) -> Axis {
+        self.width
+    }
+
+    fn height(&self) -> Axis {
+        self.height
+    }
+
+    fn storage_len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn offset(&self, (x, y): Location) -> Option<usize> {
+        if (0..self.width).contains(&x) && (0..self.height).contains(&y) {
+            // Skip over the line ending at the end of each row
+            usize::try_from((self.width + 1) * y + x).ok()
         } else {
+            None
+        }
+    }
+
+    fn at(&self, loc: Location) -> i32 {
+        self.offset(loc).map_or(
             // Out of range, return top height
-            9
+            9,
+            // Valid coordinates; the bytes are all ascii digits
+            |index| i32::from(self.data[index].saturating_sub(b'0')),
+        )
+    }
+}
+
+// Heights as numbers rather than ASCII digits, and without line endings. Because the data
+// holds the actual heights, this can hand out references to them with `Index`.
+struct DecodedHeightmap {
+    width: Axis,
+    height: Axis,
+    data: Vec<u8>,
+}
+
+impl std::ops::Index<Location> for DecodedHeightmap {
+    type Output = u8;
+
+    fn index(&self, loc: Location) -> &u8 {
+        // Out of range, return top height
+        self.offset(loc).map_or(&9, |index| &self.data[index])
+    }
+}
+
+impl Heights for DecodedHeightmap {
+    fn width(&self) -> Axis {
+        self.width
+    }
+
+    fn height(&self) -> Axis {
+        self.height
+    }
+
+    fn storage_len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn offset(&self, (x, y): Location) -> Option<usize> {
+        if (0..self.width).contains(&x) && (0..self.height).contains(&y) {
+            usize::try_from(self.width * y + x).ok()
+        } else {
+            None
         }
     }
 
-    // Takes a point, and iterates over the 4 *points* (not their values!) surrounding it.
-    // It *does* return coordinates for entries outside of the grid, because `at()` handles
-    // them properly
-    fn neighbors((x, y): Location) -> impl Iterator<Item = Location> {
-        [(1, 0), (0, 1), (-1, 0), (0, -1)]
-            .into_iter()
-            .map(move |(dx, dy)| (x + dx, y + dy))
+    fn at(&self, loc: Location) -> i32 {
+        i32::from(self[loc])
     }
 }
 
-impl<'a> std::fmt::Debug for Heightmap<'a> {
+impl std::fmt::Debug for Heightmap<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         writeln!(f)?;
         for b in self.data {
@@ -166,4 +426,18 @@ dhat: The data has been saved to dhat-heap.json, and is viewable with dhat/dh_vi
 0.00user 0.01system 0:00.02elapsed 100%CPU (0avgtext+0avgdata 10824maxresident)k
 0inputs+48outputs (0major+874minor)pagefaults 0swaps
 
+Part 2, labelling every basin at once with union-find:
+Answer: (468, 1280496)
+dhat: Total:     421,684 bytes in 14 blocks
+dhat: At t-gmax: 416,980 bytes in 6 blocks
+dhat: At t-end:  1,024 bytes in 1 blocks
+
+Same, generic over `Heights`; borrowed, then with `--decoded`:
+Answer: (468, 1280496)
+dhat: Total:     421,726 bytes in 16 blocks
+dhat: At t-gmax: 416,980 bytes in 6 blocks
+Answer: (468, 1280496)
+dhat: Total:     443,259 bytes in 25 blocks
+dhat: At t-gmax: 425,780 bytes in 7 blocks
+
 */
@@ -4,7 +4,10 @@
 #![warn(clippy::nursery)]
 //#![warn(clippy::cargo)]
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, Write};
 
 #[cfg(feature = "dhat-heap")]
 #[global_allocator]
@@ -14,110 +17,370 @@ fn main() -> Result<()> {
     #[cfg(feature = "dhat-heap")]
     let _profiler = dhat::Profiler::new_heap();
 
-    println!("Result: {:?}", process(&std::fs::read("inputs/day10.txt")?));
+    let mut pairs = Checker::BRACKETS.to_owned();
+    let mut others = Others::Reject;
+    let mut path = "inputs/day10.txt".to_owned();
+    let mut bisect = false;
+    let mut command = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => {
+                path = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --input"))?;
+            }
+            "--pairs" => {
+                pairs = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --pairs"))?
+                    .into_bytes();
+            }
+            "--others" => {
+                others = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --others"))?
+                    .parse()?;
+            }
+            "--bisect" => bisect = true,
+            _ => command.push(arg),
+        }
+    }
+    let checker = Checker::new(&pairs, others)?;
+    let mut input = BufReader::new(File::open(&path).with_context(|| format!("Opening {path}"))?);
+    let mut out = std::io::stdout().lock();
+
+    match command.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => writeln!(out, "Result: {:?}", process(&mut input, &checker, bisect)?)?,
+        ["errors"] => checker.check_stream(&mut input, |number, check| {
+            if matches!(
+                check,
+                SyntaxCheck::Corrupt { .. } | SyntaxCheck::Invalid { .. }
+            ) {
+                writeln!(out, "{number}: {check}")?;
+            }
+            Ok(())
+        })?,
+        ["report"] => checker.check_stream(&mut input, |number, check| {
+            writeln!(out, "{number}: {}", checker.report(&check))?;
+            Ok(())
+        })?,
+        _ => bail!(
+            "Unknown command {command:?}, expected `errors` or `report`, with options \
+            `--input FILE`, `--pairs OPENCLOSE...`, `--others ignore|reject` or `--bisect`"
+        ),
+    }
+
     Ok(())
 }
 
-fn process(input: &[u8]) -> (usize, usize) {
-    let (part1, mut part2) = input
-        // Inclusive split to avoid extra empty result after final newline
-        .split_inclusive(|b| *b == b'\n')
-        .map(check_line)
-        .fold((0, Vec::<usize>::new()), |(part1, mut part2), check| {
-            if let Some(score) = check.score_incomplete() {
-                part2.push(score);
+// With `bisect`, the median is found by rereading the input rather than keeping the scores
+fn process(
+    input: &mut (impl BufRead + Seek),
+    checker: &Checker,
+    bisect: bool,
+) -> Result<(usize, usize)> {
+    let mut part1 = 0;
+    let mut scores = vec![];
+    let mut count = 0;
+    let mut range = (usize::MAX, 0);
+    checker.check_stream(input, |number, check| {
+        match check {
+            SyntaxCheck::Complete => {}
+            SyntaxCheck::Corrupt { found, .. } => part1 += checker.score_error(found),
+            SyntaxCheck::Incomplete(open) => {
+                let score = checker
+                    .score_incomplete(open)
+                    .ok_or_else(|| anyhow!("Completion score overflowed"))
+                    .with_context(|| format!("Line {number}"))?;
+                count += 1;
+                range = (range.0.min(score), range.1.max(score));
+                if !bisect {
+                    scores.push(score);
+                }
             }
-            (part1 + check.score_error(), part2)
-        });
-    let mid = part2.len() / 2;
-    (part1, *part2.select_nth_unstable(mid).1)
+            check @ SyntaxCheck::Invalid { .. } => bail!("Line {number}: {check}"),
+        }
+        Ok(())
+    })?;
+    ensure!(count > 0, "No incomplete lines to score");
+
+    let mid = count / 2;
+    let part2 = if bisect {
+        bisect_median(input, checker, mid, range)?
+    } else {
+        *scores.select_nth_unstable(mid).1
+    };
+    Ok((part1, part2))
 }
 
-// NB input has trailing '\n'
-fn check_line(input: &[u8]) -> SyntaxCheck {
-    let check =
-        input.iter().try_fold(
-            Vec::<SymType>::new(),
-            |mut state, in_byte| match Sym::try_from(*in_byte) {
-                Ok(sym) => match sym.0 {
-                    (symtype, true) => {
-                        state.push(symtype);
-                        Ok(state)
-                    }
-                    (symtype, false) => match state.pop() {
-                        Some(t) if t == symtype => Ok(state),
-                        _ => Err(SyntaxCheck::Error(*in_byte)),
-                    },
-                },
-                Err(b'\n') => Ok(state),
-                Err(_e) => panic!("Unexpected input {in_byte}"),
-            },
-        );
-    match check {
-        Err(e @ SyntaxCheck::Error(_)) => e,
-        Err(e) => panic!("Impossible, resolved {e:?} too soon!"),
-        Ok(state) if state.is_empty() => SyntaxCheck::Complete,
-        Ok(state) => SyntaxCheck::Incomplete(state),
+// Binary search on the score itself: each pass rereads the whole input, counting how many
+// scores are at or below a guess. It takes up to 64 passes, but memory use doesn't depend
+// on the number of lines. Returns the score that would be at index `mid` if sorted.
+fn bisect_median(
+    input: &mut (impl BufRead + Seek),
+    checker: &Checker,
+    mid: usize,
+    (mut low, mut high): (usize, usize),
+) -> Result<usize> {
+    while low < high {
+        let guess = low + (high - low) / 2;
+        let mut at_or_below = 0;
+        input.rewind().context("Rewinding input")?;
+        checker.check_stream(input, |_number, check| {
+            if let SyntaxCheck::Incomplete(open) = check {
+                if checker.score_incomplete(open).is_some_and(|s| s <= guess) {
+                    at_or_below += 1;
+                }
+            }
+            Ok(())
+        })?;
+        if at_or_below > mid {
+            high = guess;
+        } else {
+            low = guess + 1;
+        }
     }
+    Ok(low)
 }
 
-#[derive(Debug, Clone)]
-enum SyntaxCheck {
-    Complete,
-    Incomplete(Vec<SymType>),
-    Error(u8),
+// Index into the checker's pairs
+type PairID = u8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Open(PairID),
+    Close(PairID),
+    Other,
 }
 
+// What to do with bytes that aren't part of any pair
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(u8)]
-enum SymType {
-    Round,
-    Square,
-    Curly,
-    Angle,
+enum Others {
+    Ignore,
+    Reject,
 }
 
-struct Sym((SymType, bool));
-
-impl TryFrom<u8> for Sym {
-    type Error = u8;
-
-    fn try_from(byte: u8) -> Result<Self, Self::Error> {
-        use SymType::*;
-        match byte {
-            b'(' => Ok(Self((Round, true))),
-            b'[' => Ok(Self((Square, true))),
-            b'{' => Ok(Self((Curly, true))),
-            b'<' => Ok(Self((Angle, true))),
-            b')' => Ok(Self((Round, false))),
-            b']' => Ok(Self((Square, false))),
-            b'}' => Ok(Self((Curly, false))),
-            b'>' => Ok(Self((Angle, false))),
-            _ => Err(byte),
+impl std::str::FromStr for Others {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ignore" => Ok(Self::Ignore),
+            "reject" => Ok(Self::Reject),
+            _ => bail!("Invalid policy {s:?}, expected `ignore` or `reject`"),
         }
     }
 }
 
-impl SyntaxCheck {
-    const fn score_error(&self) -> usize {
-        match *self {
-            Self::Error(b')') => 3,
-            Self::Error(b']') => 57,
-            Self::Error(b'}') => 1197,
-            Self::Error(b'>') => 25137,
+#[derive(Debug)]
+struct Checker {
+    // (open, close) bytes, in the order they're scored
+    pairs: Vec<(u8, u8)>,
+    // What each byte does, so checking is a single lookup per byte
+    classes: [Class; 256],
+    others: Others,
+}
+
+impl Checker {
+    // The puzzle's four pairs, in scoring order
+    const BRACKETS: &'static [u8] = b"()[]{}<>";
+
+    // Pairs are given as consecutive bytes, opener first, like BRACKETS
+    fn new(pairs: &[u8], others: Others) -> Result<Self> {
+        let chunks = pairs.chunks_exact(2);
+        ensure!(
+            chunks.remainder().is_empty(),
+            "Unmatched opener in pairs {:?}",
+            String::from_utf8_lossy(pairs)
+        );
+
+        let mut checker = Self {
+            pairs: vec![],
+            classes: [Class::Other; 256],
+            others,
+        };
+        for (id, pair) in chunks.enumerate() {
+            let id = PairID::try_from(id).context("Too many pairs")?;
+            for (byte, class) in [(pair[0], Class::Open(id)), (pair[1], Class::Close(id))] {
+                let slot = &mut checker.classes[usize::from(byte)];
+                ensure!(
+                    *slot == Class::Other && byte != b'\n',
+                    "Can't use {:?} in more than one place in pairs",
+                    char::from(byte)
+                );
+                *slot = class;
+            }
+            checker.pairs.push((pair[0], pair[1]));
+        }
+        ensure!(!checker.pairs.is_empty(), "No pairs to check");
+
+        Ok(checker)
+    }
+
+    // Checks each line of the input in turn, reading it a buffer at a time so memory use
+    // is the same however big the input is. The same stack of open pairs is used for every
+    // line, and lent to `each_line` for incomplete lines. Lines are numbered from 1.
+    fn check_stream(
+        &self,
+        input: &mut impl BufRead,
+        mut each_line: impl FnMut(usize, SyntaxCheck) -> Result<()>,
+    ) -> Result<()> {
+        let mut open = vec![];
+        // Set once the line is corrupt; the rest of it is skipped
+        let mut failed = None;
+        let mut offset = 0;
+        let mut number = 0;
+        loop {
+            let buffer = input.fill_buf().context("Reading input")?;
+            if buffer.is_empty() {
+                break;
+            }
+            for &byte in buffer {
+                if byte == b'\n' {
+                    number += 1;
+                    each_line(number, failed.take().unwrap_or_else(|| finish(&open)))?;
+                    open.clear();
+                    offset = 0;
+                } else {
+                    if failed.is_none() {
+                        failed = self.step(&mut open, offset, byte);
+                    }
+                    offset += 1;
+                }
+            }
+            let len = buffer.len();
+            input.consume(len);
+        }
+
+        // Last line, if it has no line ending
+        if offset > 0 {
+            each_line(number + 1, failed.unwrap_or_else(|| finish(&open)))?;
+        }
+        Ok(())
+    }
+
+    // Update the open pairs for the next byte of a line. Returns the result early if the
+    // byte makes the line corrupt or invalid.
+    fn step(
+        &self,
+        open: &mut Vec<PairID>,
+        offset: usize,
+        found: u8,
+    ) -> Option<SyntaxCheck<'static>> {
+        match self.classes[usize::from(found)] {
+            Class::Open(id) => open.push(id),
+            Class::Close(id) => match open.pop() {
+                Some(top) if top == id => {}
+                top => {
+                    return Some(SyntaxCheck::Corrupt {
+                        offset,
+                        found,
+                        // Nothing to close if the stack was empty
+                        expected: top.map(|top| self.pairs[usize::from(top)].1),
+                    });
+                }
+            },
+            Class::Other if self.others == Others::Reject => {
+                return Some(SyntaxCheck::Invalid { offset, found });
+            }
+            Class::Other => {}
+        }
+        None
+    }
+
+    // The closers that would finish off the open pairs, innermost first
+    fn completion(&self, open: &[PairID]) -> Vec<u8> {
+        open.iter()
+            .rev()
+            .map(|&id| self.pairs[usize::from(id)].1)
+            .collect()
+    }
+
+    // One line summary of a check, including the completion for incomplete lines
+    fn report(&self, check: &SyntaxCheck) -> String {
+        match check {
+            SyntaxCheck::Incomplete(open) => format!(
+                "incomplete, complete with {}",
+                String::from_utf8_lossy(&self.completion(open))
+            ),
+            _ => check.to_string(),
+        }
+    }
+
+    // Points for the puzzle's pairs; any extra pairs score nothing
+    fn score_error(&self, found: u8) -> usize {
+        const SCORES: [usize; 4] = [3, 57, 1197, 25137];
+        match self.classes[usize::from(found)] {
+            Class::Close(id) => SCORES.get(usize::from(id)).copied().unwrap_or_default(),
             _ => 0,
         }
     }
 
-    fn score_incomplete(&self) -> Option<usize> {
-        if let Self::Incomplete(rem) = self {
-            Some(
-                rem.iter()
-                    .rev()
-                    .fold(0, |acc, symtype| acc * 5 + (*symtype as usize + 1)),
-            )
-        } else {
-            None
+    // Each closer needed scores its pair's position, counting from 1, in base (pairs + 1).
+    // That's base 5 for the puzzle. None if it doesn't fit.
+    fn score_incomplete(&self, open: &[PairID]) -> Option<usize> {
+        let base = self.pairs.len() + 1;
+        open.iter().rev().try_fold(0usize, |acc, &id| {
+            acc.checked_mul(base)?.checked_add(usize::from(id) + 1)
+        })
+    }
+}
+
+// Result of checking a line that made it to the end without errors
+const fn finish(open: &[PairID]) -> SyntaxCheck<'_> {
+    if open.is_empty() {
+        SyntaxCheck::Complete
+    } else {
+        SyntaxCheck::Incomplete(open)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum SyntaxCheck<'a> {
+    Complete,
+    // The pairs still open, innermost last
+    Incomplete(&'a [PairID]),
+    // A closer that doesn't match the innermost open pair
+    Corrupt {
+        offset: usize,
+        found: u8,
+        expected: Option<u8>,
+    },
+    // A byte that isn't in any pair, when those are rejected
+    Invalid {
+        offset: usize,
+        found: u8,
+    },
+}
+
+impl fmt::Display for SyntaxCheck<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Complete => write!(f, "complete"),
+            Self::Incomplete(open) => write!(f, "incomplete, {} open", open.len()),
+            Self::Corrupt {
+                offset,
+                found,
+                expected: Some(expected),
+            } => write!(
+                f,
+                "corrupt at byte {offset}, expected {:?} but found {:?}",
+                char::from(expected),
+                char::from(found)
+            ),
+            Self::Corrupt {
+                offset,
+                found,
+                expected: None,
+            } => write!(
+                f,
+                "corrupt at byte {offset}, found {:?} with nothing open",
+                char::from(found)
+            ),
+            Self::Invalid { offset, found } => {
+                write!(f, "invalid byte {:?} at byte {offset}", char::from(found))
+            }
         }
     }
 }
@@ -140,4 +403,27 @@ dhat: The data has been saved to dhat-heap.json, and is viewable with dhat/dh_vi
 0.00user 0.00system 0:00.00elapsed 100%CPU (0avgtext+0avgdata 10308maxresident)k
 0inputs+80outputs (0major+819minor)pagefaults 0swaps
 
+Configurable pairs, with a byte lookup table instead of Sym::try_from:
+Result: (339537, 2412013412)
+dhat: Total:     14,747 bytes in 215 blocks
+dhat: At t-gmax: 11,289 bytes in 5 blocks
+
+Streaming from a BufReader, with one stack for all lines (release):
+Result: (339537, 2412013412)
+dhat: Total:     10,379 bytes in 16 blocks
+dhat: At t-gmax: 9,848 bytes in 8 blocks
+
+`--bisect`, median from rereading the input instead of keeping the scores:
+Result: (339537, 2412013412)
+dhat: Total:     10,259 bytes in 82 blocks
+dhat: At t-gmax: 9,360 bytes in 7 blocks
+
+Input repeated 1,000 times (10 MB), kept scores then `--bisect`:
+Result: (339537000, 2412013412)
+dhat: At t-gmax: 533,622 bytes in 8 blocks
+Result: (339537000, 2412013412)
+dhat: At t-gmax: 9,358 bytes in 7 blocks
+
+Input repeated 20,000 times (204 MB), no dhat: 1.01s kept scores, 30.1s `--bisect`
+
 */
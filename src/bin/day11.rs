@@ -1,71 +1,319 @@
-#![feature(mixed_integer_ops)]
 #![deny(clippy::all)]
 #![warn(clippy::pedantic)]
 //#![warn(clippy::restriction)]
 #![warn(clippy::nursery)]
 //#![warn(clippy::cargo)]
 
-use anyhow::{anyhow, Result};
-use itertools::Itertools;
-use std::collections::VecDeque;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::Path;
+use std::time::Duration;
 
 #[cfg(feature = "dhat-heap")]
 #[global_allocator]
 static ALLOC: dhat::Alloc = dhat::Alloc;
 
+// Part 1 counts the flashes in this many steps
+const PART1_STEPS: usize = 100;
+
 fn main() -> Result<()> {
     #[cfg(feature = "dhat-heap")]
     let _profiler = dhat::Profiler::new_heap();
 
-    println!(
-        "Result: {:?}",
-        process(&std::fs::read_to_string("inputs/day11.txt")?)
-    );
+    let mut input = "inputs/day11.txt".to_owned();
+    let mut limit = 10_000;
+    let mut rules = Rules::default();
+    let mut command = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => {
+                input = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --input"))?;
+            }
+            "--steps" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --steps"))?;
+                limit = value
+                    .parse()
+                    .with_context(|| format!("Invalid step limit {value:?}"))?;
+            }
+            "--threshold" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --threshold"))?;
+                rules.threshold = value
+                    .parse()
+                    .with_context(|| format!("Invalid threshold {value:?}"))?;
+            }
+            "--neighborhood" => {
+                rules.neighborhood = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --neighborhood"))?
+                    .parse()?;
+            }
+            "--wrap" => rules.wrap = true,
+            _ => command.push(arg),
+        }
+    }
+    let input = std::fs::read_to_string(&input).with_context(|| format!("Reading {input}"))?;
+
+    match command.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => println!("Result: {:?}", process(&input, rules, limit)?),
+        ["ansi", ref delay @ ..] if delay.len() <= 1 => {
+            let delay = delay.first().unwrap_or(&"100");
+            let delay = Duration::from_millis(
+                delay
+                    .parse()
+                    .with_context(|| format!("Invalid delay {delay:?}"))?,
+            );
+            let mut out = std::io::stdout().lock();
+            animate(&input, rules, limit, |step, grid, flashes| {
+                // Home the cursor and clear the screen, so each frame replaces the last
+                write!(
+                    out,
+                    "\x1b[H\x1b[2JStep {step}, {flashes} flashes\n{}",
+                    grid.ansi(step > 0, rules.threshold)
+                )?;
+                out.flush()?;
+                std::thread::sleep(delay);
+                Ok(())
+            })?;
+        }
+        ["pgm", ref dir @ ..] if dir.len() <= 1 => {
+            let dir = Path::new(dir.first().unwrap_or(&"day11-frames"));
+            std::fs::create_dir_all(dir).with_context(|| format!("Creating {}", dir.display()))?;
+            animate(&input, rules, limit, |step, grid, _flashes| {
+                let path = dir.join(format!("step{step:05}.pgm"));
+                grid.write_pgm(&path, step > 0, rules.threshold)
+                    .with_context(|| format!("Writing {}", path.display()))
+            })?;
+        }
+        _ => bail!(
+            "Unknown command {command:?}, expected `ansi [DELAY_MS]` or `pgm [DIR]`, \
+            with options `--input FILE`, `--steps N`, `--threshold N`, \
+            `--neighborhood moore|von-neumann` or `--wrap`"
+        ),
+    }
+
     Ok(())
 }
 
-fn process(input: &str) -> Result<(usize, Option<usize>)> {
-    let mut octopi = Octopi(input.parse()?);
-    //dbg!(&octopi);
-    let mut flashes = 0;
-    let mut first_time = None;
-    // For Part 1, just make this range `0..100`
-    for step in 0.. {
+#[derive(Debug, Default)]
+struct Report {
+    // Total flashes in the first PART1_STEPS steps, or all of them if the limit is lower
+    flashes: usize,
+    // First step after which every octopus has just flashed
+    synchronised: Option<usize>,
+    // If the grid repeats: (step where the repeating part starts, period)
+    cycle: Option<(usize, usize)>,
+    // Number of steps simulated
+    steps: usize,
+}
+
+// Runs until both parts are answered, or a repeated grid shows that the octopi will never
+// synchronise, or the step limit is reached.
+fn process(input: &str, rules: Rules, limit: usize) -> Result<Report> {
+    let start: DigitGrid = input.parse()?;
+    let mut octopi = Octopi::new(start.clone(), rules)?;
+    let mut report = Report::default();
+
+    // Brent's algorithm: compare each grid with a saved one, which is moved forward to the
+    // current grid at every power of two steps. A match gives the period.
+    let mut saved = start.clone();
+    let mut power = 1;
+    let mut since_saved = 0;
+
+    for step in 1..=limit {
         let this_time = octopi.step();
-        //eprintln!("{step} {this_time} {}", octopi.size());
-        flashes += this_time;
-        //dbg!(&octopi);
+        report.steps = step;
+        if step <= PART1_STEPS {
+            report.flashes += this_time;
+        }
         if this_time == octopi.size() {
-            first_time = first_time.or(Some(step + 1));
+            report.synchronised = report.synchronised.or(Some(step));
+        }
+
+        if report.cycle.is_none() {
+            since_saved += 1;
+            if octopi.grid == saved {
+                report.cycle = Some((cycle_start(&octopi, &start, since_saved), since_saved));
+            } else if since_saved == power {
+                saved.clone_from(&octopi.grid);
+                power *= 2;
+                since_saved = 0;
+            }
+        }
+
+        // Once a cycle is found, every state the grid will ever be in has been seen
+        if step >= PART1_STEPS && (report.synchronised.is_some() || report.cycle.is_some()) {
             break;
         }
     }
-    Ok((flashes, first_time))
+
+    Ok(report)
 }
 
-#[derive(Debug)]
-struct Octopi(DigitGrid);
+// Calls `frame` with the grid before the first step, and after each step until they all
+// flash together or the step limit is reached
+fn animate(
+    input: &str,
+    rules: Rules,
+    limit: usize,
+    mut frame: impl FnMut(usize, &DigitGrid, usize) -> Result<()>,
+) -> Result<()> {
+    let mut octopi = Octopi::new(input.parse()?, rules)?;
+    frame(0, &octopi.grid, 0)?;
+    for step in 1..=limit {
+        let flashes = octopi.step();
+        frame(step, &octopi.grid, flashes)?;
+        if flashes == octopi.size() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Second half of Brent's algorithm: start one copy `period` steps ahead of the other, then
+// step them together until they match, which is where the cycle starts.
+fn cycle_start(octopi: &Octopi, start: &DigitGrid, period: usize) -> usize {
+    let mut behind = Octopi {
+        grid: start.clone(),
+        ..octopi.clone()
+    };
+    let mut ahead = behind.clone();
+    for _ in 0..period {
+        ahead.step();
+    }
+    let mut step = 0;
+    while behind.grid != ahead.grid {
+        behind.step();
+        ahead.step();
+        step += 1;
+    }
+    step
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Neighborhood {
+    // The 4 orthogonal cells
+    VonNeumann,
+    // All 8 cells around, including diagonals (the puzzle's rule)
+    Moore,
+}
+
+impl Neighborhood {
+    const fn offsets(self) -> &'static [(isize, isize)] {
+        match self {
+            Self::VonNeumann => &[(0, -1), (1, 0), (0, 1), (-1, 0)],
+            Self::Moore => &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (1, 0),
+                (1, 1),
+                (0, 1),
+                (-1, 1),
+                (-1, 0),
+            ],
+        }
+    }
+}
+
+impl std::str::FromStr for Neighborhood {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "von-neumann" => Ok(Self::VonNeumann),
+            "moore" => Ok(Self::Moore),
+            _ => bail!("Invalid neighborhood {s:?}, expected `moore` or `von-neumann`"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rules {
+    // An octopus flashes when its energy goes over this
+    threshold: u8,
+    neighborhood: Neighborhood,
+    // Whether the edges join up with the opposite edges, like a torus
+    wrap: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            threshold: 9,
+            neighborhood: Neighborhood::Moore,
+            wrap: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Octopi {
+    grid: DigitGrid,
+    rules: Rules,
+    // Cells that have flashed but not yet passed it on to their neighbors. Kept between
+    // steps so its storage is reused; each cell flashes at most once a step, so it never
+    // needs more than one slot per cell.
+    flashing: Vec<usize>,
+}
 
 impl Octopi {
-    fn size(&self) -> usize {
-        self.0 .0.len() * self.0 .0[0].len()
+    fn new(grid: DigitGrid, rules: Rules) -> Result<Self> {
+        // Energy is shown as a single base 36 digit
+        ensure!(
+            (1..36).contains(&rules.threshold),
+            "Threshold must be between 1 and 35"
+        );
+        ensure!(
+            grid.cells.iter().all(|&c| c <= rules.threshold),
+            "Input has energy levels over the threshold of {}",
+            rules.threshold
+        );
+        let flashing = Vec::with_capacity(grid.cells.len());
+        Ok(Self {
+            grid,
+            rules,
+            flashing,
+        })
+    }
+
+    const fn size(&self) -> usize {
+        self.grid.cells.len()
     }
+
     fn step(&mut self) -> usize {
-        // Initially, we increment every cell: they're all active
-        let mut active: VecDeque<_> = self.0.coords().collect();
+        let flash = self.rules.threshold + 1;
+
+        // Initially, we increment every cell
+        for (i, c) in self.grid.cells.iter_mut().enumerate() {
+            *c += 1;
+            if *c == flash {
+                self.flashing.push(i);
+            }
+        }
+
         let mut flashes = 0;
-        while let Some(pos) = active.pop_front() {
-            *self.0.at_mut(pos) += 1;
-            if *self.0.at(pos) == 9 + 1 {
-                flashes += 1;
-                let neighbors = self.0.neighbors8(pos).into_iter().filter_map(identity);
-                //dbg!(neighbors.clone().collect::<Vec<_>>());
-                active.extend(neighbors);
+        while let Some(i) = self.flashing.pop() {
+            flashes += 1;
+            for n in self.grid.neighbors(i, self.rules).into_iter().flatten() {
+                let c = &mut self.grid.cells[n];
+                // Cells that already flashed keep counting up, but never flash again
+                *c = c.saturating_add(1);
+                if *c == flash {
+                    self.flashing.push(n);
+                }
             }
-            //eprintln!("({}, {}) = {} / {}", pos.0, pos.1, *self.0.at(pos), flashes);
         }
-        for c in self.0.cells_mut() {
-            if *c > 9 {
+
+        for c in &mut self.grid.cells {
+            if *c >= flash {
                 *c = 0;
             }
         }
@@ -73,88 +321,126 @@ impl Octopi {
     }
 }
 
-struct DigitGrid(Vec<Vec<u8>>);
+// Rows of digits, stored one after the other in a single Vec
+#[derive(Clone, PartialEq, Eq)]
+struct DigitGrid {
+    width: usize,
+    cells: Vec<u8>,
+}
+
+// Pixels along each side of a cell in PGM frames
+const CELL_PIXELS: usize = 8;
 
 impl DigitGrid {
-    fn at(&self, (x, y): (usize, usize)) -> &u8 {
-        &self.0[y][x]
+    fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.cells.chunks(self.width)
     }
 
-    fn at_mut(&mut self, (x, y): (usize, usize)) -> &mut u8 {
-        &mut self.0[y][x]
-    }
+    // Indexes of the cells around a cell. Without wrapping, they're None off the edges. With
+    // it on a very small grid, the same cell can come up more than once, or be the cell
+    // itself.
+    fn neighbors(&self, i: usize, rules: Rules) -> [Option<usize>; 8] {
+        let (width, height) = (self.width, self.cells.len() / self.width);
+        let (x, y) = (i % width, i / width);
+        let mut neighbors = [None; 8];
 
-    fn coords(&self) -> impl Iterator<Item = (usize, usize)> {
-        (0..self.0[0].len()).cartesian_product(0..self.0.len())
+        for (n, &(dx, dy)) in neighbors.iter_mut().zip(rules.neighborhood.offsets()) {
+            *n = if rules.wrap {
+                let x = (x + width).saturating_add_signed(dx) % width;
+                let y = (y + height).saturating_add_signed(dy) % height;
+                Some(y * width + x)
+            } else {
+                x.checked_add_signed(dx)
+                    .filter(|&x| x < width)
+                    .zip(y.checked_add_signed(dy).filter(|&y| y < height))
+                    .map(|(x, y)| y * width + x)
+            };
+        }
+        neighbors
     }
 
-    fn cells_mut(&mut self) -> impl Iterator<Item = &mut u8> {
-        self.0.iter_mut().flatten()
+    // After a step, the only zeros are the octopi that just flashed, so with `flashed` set
+    // those are shown in bold white. Everything else is a shade of grey by energy level, up
+    // to the threshold.
+    fn ansi(&self, flashed: bool, threshold: u8) -> String {
+        let mut out = String::new();
+        for row in self.rows() {
+            for &digit in row {
+                if flashed && digit == 0 {
+                    write!(out, "\x1b[1;97m0").unwrap();
+                } else {
+                    // Greyscale part of the 256 colour palette runs from 232 to 255
+                    let grey = 234 + u16::from(digit) * 18 / u16::from(threshold);
+                    write!(out, "\x1b[0;38;5;{grey}m{}", digit_char(digit)).unwrap();
+                }
+            }
+            writeln!(out, "\x1b[0m").unwrap();
+        }
+        out
     }
 
-    fn neighbors8(&self, (x, y): (usize, usize)) -> [Option<(usize, usize)>; 8] {
-        let mut neighbors = [None; 8];
-
-        [
-            (-1isize, -1isize),
-            (0, -1),
-            (1, -1),
-            (1, 0),
-            (1, 1),
-            (0, 1),
-            (-1, 1),
-            (-1, 0),
-        ]
-        .iter()
-        .map(|&(dx, dy)| {
-            x.checked_add_signed(dx)
-                .and_then(|x| if x >= self.0[0].len() { None } else { Some(x) })
-                .and_then(|x| {
-                    y.checked_add_signed(dy)
-                        .and_then(|y| if y >= self.0.len() { None } else { Some(y) })
-                        .map(|y| (x, y))
+    // The same as `ansi`, but as a PGM frame, with flashes in full white
+    fn write_pgm(&self, path: &Path, flashed: bool, threshold: u8) -> Result<()> {
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let height = self.cells.len() / self.width;
+        write!(
+            out,
+            "P5\n{} {}\n255\n",
+            self.width * CELL_PIXELS,
+            height * CELL_PIXELS
+        )?;
+        for row in self.rows() {
+            let pixels = row
+                .iter()
+                .flat_map(|&digit| {
+                    let shade = if flashed && digit == 0 {
+                        255
+                    } else {
+                        // Keep full white for flashes
+                        u8::try_from(u16::from(digit) * 200 / u16::from(threshold)).unwrap()
+                    };
+                    [shade; CELL_PIXELS]
                 })
-        })
-        .enumerate()
-        .for_each(|(i, n)| neighbors[i] = n);
-        neighbors
+                .collect::<Vec<u8>>();
+            for _ in 0..CELL_PIXELS {
+                out.write_all(&pixels)?;
+            }
+        }
+        out.flush()?;
+        Ok(())
     }
 }
 
 impl std::str::FromStr for DigitGrid {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self> {
-        let data = s
+        let width = s.lines().next().map_or(0, str::len);
+        let cells = s
             .lines()
-            .map(|line| {
-                line.chars()
-                    .map(|c| {
-                        c.to_digit(10)
-                            .map(|d| d.try_into().expect("Infallible cast {d} to u8"))
-                            .ok_or_else(|| anyhow!("Invalid input char {c}"))
-                    })
-                    .collect::<Result<Vec<_>>>()
+            .flat_map(str::chars)
+            .map(|c| {
+                c.to_digit(10)
+                    .map(|d| u8::try_from(d).expect("Digits fit in u8"))
+                    .ok_or_else(|| anyhow!("Invalid input char {c}"))
             })
-            .collect::<Result<Vec<Vec<_>>>>()?;
-        if data.is_empty()
-            || data[0].is_empty()
-            || !data.iter().all(|row| row.len() == data[0].len())
-        {
-            panic!("Input is not a MxN grid of digits: {data:#?}");
-        }
+            .collect::<Result<Vec<_>>>()?;
+        ensure!(
+            width > 0 && s.lines().all(|line| line.len() == width),
+            "Input is not a MxN grid of digits: {s:?}"
+        );
 
-        Ok(Self(data))
+        Ok(Self { width, cells })
     }
 }
 
 impl std::fmt::Display for DigitGrid {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        for row in &self.0 {
+        for row in self.rows() {
             writeln!(
                 f,
                 "{}",
                 row.iter()
-                    .map(|digit| (digit + b'0') as char)
+                    .map(|&digit| digit_char(digit))
                     .collect::<String>()
             )?;
         }
@@ -162,14 +448,37 @@ impl std::fmt::Display for DigitGrid {
     }
 }
 
+// Energy levels can go over 9 with a higher threshold
+fn digit_char(digit: u8) -> char {
+    char::from_digit(u32::from(digit), 36).unwrap_or('?')
+}
+
 impl std::fmt::Debug for DigitGrid {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self)
+        write!(f, "{self}")
     }
 }
 
-const fn identity<A>(a: A) -> A {
-    a
+#[cfg(all(test, feature = "dhat-heap"))]
+mod tests {
+    use super::*;
+
+    // The flash stack is allocated up front, so stepping should never allocate
+    #[test]
+    fn step_does_not_allocate() {
+        let _profiler = dhat::Profiler::builder().testing().build();
+        let grid = "5483143223\n2745854711\n5264556173\n6141336146\n6357385478\n\
+                    4167524645\n2176841721\n6882881134\n4846848554\n5283751526";
+        let mut octopi = Octopi::new(grid.parse().unwrap(), Rules::default()).unwrap();
+        octopi.step();
+
+        let before = dhat::HeapStats::get();
+        for _ in 0..200 {
+            octopi.step();
+        }
+        let after = dhat::HeapStats::get();
+        dhat::assert_eq!(after.total_blocks, before.total_blocks);
+    }
 }
 
 /*
@@ -191,4 +500,32 @@ dhat: The data has been saved to dhat-heap.json, and is viewable with dhat/dh_vi
 0.00user 0.00system 0:00.01elapsed 90%CPU (0avgtext+0avgdata 10668maxresident)k
 0inputs+88outputs (0major+852minor)pagefaults 0swaps
 
+Both parts in one run, with Brent's cycle detection cloning the grid at each power of two:
+Result: Report { flashes: 1599, synchronised: Some(418), cycle: None, steps: 418 }
+dhat: Total:     1,048,706 bytes in 673 blocks
+dhat: At t-gmax: 4,574 bytes in 37 blocks
+
+A 2x1 grid of `80` never synchronises:
+Result: Report { flashes: 22, synchronised: None, cycle: Some((0, 9)), steps: 100 }
+
+Animation: `day11 ansi [DELAY_MS]` plays it in the terminal, and `day11 pgm [DIR]` writes
+one image per step, until they all flash together. The sample input gives 196 frames.
+
+Flat grid, and a flash stack in Octopi allocated once instead of a VecDeque per step.
+With `--features dhat-heap`, a test checks that no step allocates:
+Result: Report { flashes: 1599, synchronised: Some(418), cycle: None, steps: 418 }
+dhat: Total:     3,313 bytes in 23 blocks
+dhat: At t-gmax: 1,426 bytes in 8 blocks
+
+Rule variants (`--threshold N`, `--neighborhood moore|von-neumann`, `--wrap`). None of
+these synchronise with my input, so they all end in a cycle:
+`--wrap`:
+Result: Report { flashes: 1583, synchronised: None, cycle: Some((60, 7)), steps: 100 }
+`--neighborhood von-neumann`:
+Result: Report { flashes: 1290, synchronised: None, cycle: Some((84, 8)), steps: 135 }
+`--threshold 15`:
+Result: Report { flashes: 844, synchronised: None, cycle: Some((1570, 13)), steps: 2060 }
+`--threshold 15 --wrap --neighborhood von-neumann`:
+Result: Report { flashes: 720, synchronised: None, cycle: Some((219, 14)), steps: 269 }
+
 */
@@ -4,30 +4,223 @@
 #![warn(clippy::nursery)]
 //#![warn(clippy::cargo)]
 
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write as _};
+use std::rc::Rc;
 
 #[cfg(feature = "dhat-heap")]
 #[global_allocator]
 static ALLOC: dhat::Alloc = dhat::Alloc;
 
-fn main() {
+fn main() -> Result<()> {
     #[cfg(feature = "dhat-heap")]
     let _profiler = dhat::Profiler::new_heap();
 
-    println!(
-        "Result: {:?}",
-        process(&std::fs::read_to_string("inputs/day12.txt").unwrap())
-    );
+    let mut input = "inputs/day12.txt".to_owned();
+    let mut enumerate = false;
+    let mut from = "start".to_owned();
+    let mut to = "end".to_owned();
+    let mut policy = VisitPolicy::Revisits(1);
+    let mut max_length = 1_000;
+    let mut command = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => {
+                input = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --input"))?;
+            }
+            "--enumerate" => enumerate = true,
+            "--from" => {
+                from = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --from"))?;
+            }
+            "--to" => {
+                to = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --to"))?;
+            }
+            "--visits" => {
+                policy = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --visits"))?
+                    .parse()?;
+            }
+            "--max-length" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --max-length"))?;
+                max_length = value
+                    .parse()
+                    .with_context(|| format!("Invalid maximum path length {value:?}"))?;
+            }
+            _ => command.push(arg),
+        }
+    }
+
+    let input = std::fs::read_to_string(&input).with_context(|| format!("Reading {input}"))?;
+    let caves = Caves::new(&input)?;
+    let search = Search {
+        from: &from,
+        to: &to,
+        policy,
+        max_length,
+    };
+    match command.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => println!("Result: {:?}", process(&caves, &search, enumerate)?),
+        ["paths", ref limit @ ..] if limit.len() <= 1 => {
+            let limit = match limit.first() {
+                Some(n) => n
+                    .parse()
+                    .with_context(|| format!("Invalid number of paths {n:?}"))?,
+                None => usize::MAX,
+            };
+            for path in caves.paths(&search)?.take(limit) {
+                println!("{}", path?.display(&caves));
+            }
+        }
+        ["dot", ref rest @ ..] if rest.len() <= 2 => {
+            let file = rest.first().unwrap_or(&"day12.dot");
+            // Optionally highlight the Nth path, counting from 1 as `paths` prints them
+            let path = match rest.get(1) {
+                Some(n) => {
+                    let n: usize = n
+                        .parse()
+                        .with_context(|| format!("Invalid path number {n:?}"))?;
+                    let path = caves
+                        .paths(&search)?
+                        .nth(n.saturating_sub(1))
+                        .ok_or_else(|| anyhow!("There's no path number {n}"))??;
+                    Some(path)
+                }
+                None => None,
+            };
+            std::fs::write(file, caves.to_dot(&search, path.as_ref())?)
+                .with_context(|| format!("Writing {file}"))?;
+        }
+        _ => bail!(
+            "Unknown command {command:?}, expected `paths [N]` or `dot [FILE [PATH]]`, \
+            with options `--input FILE`, \
+            `--enumerate`, `--from CAVE`, `--to CAVE`, `--max-length N` or \
+            `--visits none|one-double|up-to=K|CAVE=N,CAVE=N,...`"
+        ),
+    }
+    Ok(())
 }
 
-fn process(input: &str) -> usize {
-    let caves = Caves::new(input);
+// Counts the paths, or with `enumerate`, finds every path and counts those
+fn process(caves: &Caves, search: &Search, enumerate: bool) -> Result<usize> {
     //dbg!(&caves);
-    let paths = caves.exhaust("start", "end");
-    paths.len()
+    if enumerate {
+        caves
+            .paths(search)?
+            .try_fold(0, |count, path| path.map(|_| count + 1))
+    } else {
+        caves.count(search)
+    }
+}
+
+#[derive(Debug)]
+struct Search<'a> {
+    from: &'a str,
+    to: &'a str,
+    policy: VisitPolicy,
+    // Longest path to follow, counting caves, before giving up with an error. It stops
+    // runaway searches, such as when two big caves are connected; the search's memory use
+    // also grows with the path length. Only enumerating uses it: counting spots endless
+    // paths by coming back to a state it's still counting, so it can count paths longer
+    // than this.
+    max_length: usize,
+}
+
+#[derive(Debug, Clone)]
+enum VisitPolicy {
+    // Each small cave can be visited once, plus this many extra visits to small caves in
+    // total. Part 1 allows none, and part 2 allows one.
+    Revisits(usize),
+    // Visits allowed to each of the named small caves; any others can be visited once
+    PerCave(Vec<(String, usize)>),
+}
+
+impl std::str::FromStr for VisitPolicy {
+    type Err = anyhow::Error;
+
+    // One of "none", "one-double", "up-to=K", or a list of limits like "b=2,c=3"
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || format!("Invalid visit policy {s:?}");
+        match s {
+            "none" => Ok(Self::Revisits(0)),
+            "one-double" => Ok(Self::Revisits(1)),
+            _ => {
+                if let Some(k) = s.strip_prefix("up-to=") {
+                    return Ok(Self::Revisits(k.parse().with_context(invalid)?));
+                }
+                s.split(',')
+                    .map(|limit| {
+                        let (name, n) = limit.split_once('=').with_context(invalid)?;
+                        Ok((name.to_owned(), n.parse().with_context(invalid)?))
+                    })
+                    .collect::<Result<_>>()
+                    .map(Self::PerCave)
+            }
+        }
+    }
 }
 
-type CaveID = u8;
+impl VisitPolicy {
+    fn limits(&self, caves: &Caves) -> Result<Limits> {
+        let mut limits = Limits {
+            visits: caves
+                .data
+                .iter()
+                .map(|c| if c.is_small { 1 } else { usize::MAX })
+                .collect(),
+            revisits: 0,
+        };
+        match self {
+            Self::Revisits(k) => limits.revisits = *k,
+            Self::PerCave(per_cave) => {
+                for (name, n) in per_cave {
+                    let id = caves.id(name)?;
+                    ensure!(
+                        caves.data[id as usize].is_small,
+                        "Only small caves have visit limits, not {name:?}"
+                    );
+                    limits.visits[id as usize] = *n;
+                }
+            }
+        }
+        Ok(limits)
+    }
+}
+
+// A VisitPolicy for a particular set of caves
+#[derive(Debug)]
+struct Limits {
+    // Visits allowed to each cave before using up a revisit; unlimited for big caves
+    visits: Vec<usize>,
+    // Extra visits to small caves allowed in total
+    revisits: usize,
+}
+
+impl Limits {
+    // Whether a path that has been to a cave `visits` times, and has had `used` revisits,
+    // can go into it again. If so, returns the revisits used afterwards.
+    fn enter(&self, cave: CaveID, visits: usize, used: usize) -> Option<usize> {
+        if visits < self.visits[cave as usize] {
+            Some(used)
+        } else if used < self.revisits {
+            Some(used + 1)
+        } else {
+            None
+        }
+    }
+}
+
+type CaveID = u32;
 
 #[derive(Debug)]
 struct Caves {
@@ -36,128 +229,416 @@ struct Caves {
 }
 
 impl Caves {
-    fn new(input: &str) -> Self {
+    fn new(input: &str) -> Result<Self> {
         let mut caves = Self {
             by_name: HashMap::new(),
             data: vec![],
         };
-        for line in input.lines() {
-            let (a, b) = line.split_once('-').unwrap();
-            caves.connect(a, b);
+        for (i, line) in input.lines().enumerate() {
+            let (a, b) = line
+                .split_once('-')
+                .ok_or_else(|| anyhow!("Expected CAVE-CAVE, got {line:?}"))
+                .with_context(|| format!("Line {}", i + 1))?;
+            caves
+                .connect(a, b)
+                .with_context(|| format!("Line {}", i + 1))?;
         }
 
-        caves
+        Ok(caves)
     }
 
-    fn get_cave_id(&mut self, name: &str) -> CaveID {
-        *self.by_name.entry(name.to_owned()).or_insert_with(|| {
-            let id = self.data.len();
-            let id = id
-                .try_into()
-                .expect("Too many caves to fit in u8, at '{name}'");
-            self.data.push(Cave {
-                id,
-                name: name.to_owned(),
-                is_small: name.chars().next().unwrap().is_ascii_lowercase(),
-                exits: Vec::new(),
-            });
-            id
-        })
+    fn get_cave_id(&mut self, name: &str) -> Result<CaveID> {
+        if let Some(&id) = self.by_name.get(name) {
+            return Ok(id);
+        }
+
+        let first = name
+            .chars()
+            .next()
+            .ok_or_else(|| anyhow!("Empty cave name"))?;
+        let id = CaveID::try_from(self.data.len())
+            .with_context(|| format!("Too many caves, at {name:?}"))?;
+        self.data.push(Cave {
+            id,
+            name: name.to_owned(),
+            is_small: first.is_ascii_lowercase(),
+            exits: Vec::new(),
+        });
+        self.by_name.insert(name.to_owned(), id);
+        Ok(id)
     }
 
-    fn connect(&mut self, a: &str, b: &str) {
-        let a = self.get_cave_id(a);
-        let b = self.get_cave_id(b);
+    fn connect(&mut self, a: &str, b: &str) -> Result<()> {
+        let a = self.get_cave_id(a)?;
+        let b = self.get_cave_id(b)?;
         self.data[a as usize].exits.push(b);
         self.data[b as usize].exits.push(a);
+        Ok(())
     }
 
-    fn walk(&self, start: CaveID, goal: CaveID) -> Vec<Vec<CaveID>> {
-        let mut solutions = vec![];
-        let mut todo = vec![];
-        todo.push(vec![start]);
+    fn id(&self, name: &str) -> Result<CaveID> {
+        self.by_name
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("No cave named {name:?}"))
+    }
+
+    // Graphviz source for the caves: small ones are ellipses, big ones are boxes, and the
+    // start and end of the search are filled in. A path can be drawn over it in red.
+    fn to_dot(&self, search: &Search, path: Option<&Path>) -> Result<String> {
+        let ends = [self.id(search.from)?, self.id(search.to)?];
+        let (on_path, steps) = path.map_or_else(Default::default, |path| {
+            let caves = path.to_vec();
+            let steps = caves
+                .windows(2)
+                .map(|step| (step[0].min(step[1]), step[0].max(step[1])))
+                .collect::<HashSet<_>>();
+            (caves.into_iter().collect::<HashSet<_>>(), steps)
+        });
+
+        let mut dot = String::new();
+        writeln!(dot, "graph caves {{")?;
+        for cave in &self.data {
+            let shape = if cave.is_small { "ellipse" } else { "box" };
+            write!(
+                dot,
+                "    c{} [label={:?}, shape={shape}",
+                cave.id, cave.name
+            )?;
+            if ends.contains(&cave.id) {
+                write!(dot, ", style=filled, fillcolor=lightblue")?;
+            }
+            if on_path.contains(&cave.id) {
+                write!(dot, ", color=red, penwidth=2")?;
+            }
+            writeln!(dot, "];")?;
+        }
+        for cave in &self.data {
+            // Each connection is in both caves' exits, so only write it from one side
+            for &exit in cave.exits.iter().filter(|&&exit| cave.id < exit) {
+                write!(dot, "    c{} -- c{exit}", cave.id)?;
+                if steps.contains(&(cave.id, exit)) {
+                    write!(dot, " [color=red, penwidth=2]")?;
+                }
+                writeln!(dot, ";")?;
+            }
+        }
+        writeln!(dot, "}}")?;
+
+        Ok(dot)
+    }
+
+    // Every path from start to end, found one at a time
+    fn paths(&self, search: &Search) -> Result<PathIter<'_>> {
+        let start = self.id(search.from)?;
+        Ok(PathIter {
+            caves: self,
+            start,
+            goal: self.id(search.to)?,
+            limits: search.policy.limits(self)?,
+            max_length: search.max_length,
+            todo: vec![Path::new(start)],
+        })
+    }
+
+    // Number of paths, without building them. The number of ways to finish from a cave
+    // only depends on how many times each small cave has been visited and how many
+    // revisits have been used, so the count for each of those states is only worked out
+    // once. Visits past a cave's own limit don't need counting, since going back in will
+    // use a revisit either way.
+    fn count(&self, search: &Search) -> Result<usize> {
+        let limits = search.policy.limits(self)?;
+
+        // Each small cave gets a byte for its visit count
+        let mut slots = vec![None; self.data.len()];
+        let mut small = 0;
+        for cave in self.data.iter().filter(|c| c.is_small) {
+            ensure!(
+                limits.visits[cave.id as usize] <= u8::MAX.into(),
+                "Too many visits allowed to {:?} to count paths, try enumerating them",
+                cave.name
+            );
+            slots[cave.id as usize] = Some(small);
+            small += 1;
+        }
+
+        let start = self.id(search.from)?;
+        let mut counter = PathCounter {
+            caves: self,
+            start,
+            goal: self.id(search.to)?,
+            limits,
+            slots,
+            counts: vec![0; small],
+            memo: HashMap::new(),
+        };
+        counter.count()
+    }
+}
 
-        'todo: while let Some(path) = todo.pop() {
-            let cave = *path.last().unwrap();
+// A path is a persistent list, from the last cave back to the first. Extending a path
+// makes a new head, which shares the rest of the path with every other path from the same
+// point, so nothing is copied.
+#[derive(Debug, Clone)]
+struct Path(Rc<PathNode>);
 
-            if cave == goal {
+#[derive(Debug)]
+struct PathNode {
+    cave: CaveID,
+    // Extra visits to small caves so far
+    revisits: usize,
+    len: usize,
+    prev: Option<Path>,
+}
+
+impl Path {
+    fn new(cave: CaveID) -> Self {
+        Self(Rc::new(PathNode {
+            cave,
+            revisits: 0,
+            len: 1,
+            prev: None,
+        }))
+    }
+
+    fn push(&self, cave: CaveID, revisits: usize) -> Self {
+        Self(Rc::new(PathNode {
+            cave,
+            revisits,
+            len: self.0.len + 1,
+            prev: Some(self.clone()),
+        }))
+    }
+
+    fn last(&self) -> CaveID {
+        self.0.cave
+    }
+
+    // Caves from the end of the path back to the start
+    fn rev(&self) -> impl Iterator<Item = CaveID> + '_ {
+        std::iter::successors(Some(&*self.0), |node| node.prev.as_ref().map(|p| &*p.0))
+            .map(|node| node.cave)
+    }
+
+    fn to_vec(&self) -> Vec<CaveID> {
+        let mut caves: Vec<_> = self.rev().collect();
+        caves.reverse();
+        caves
+    }
+
+    fn display<'a>(&'a self, caves: &'a Caves) -> impl fmt::Display + 'a {
+        struct Names<'a>(&'a Path, &'a Caves);
+        impl fmt::Display for Names<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                for (i, cave) in self.0.to_vec().into_iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "," };
+                    write!(f, "{sep}{}", self.1.data[cave as usize].name)?;
+                }
+                Ok(())
+            }
+        }
+        Names(self, caves)
+    }
+}
+
+// Depth first search for paths, stopping whenever one is found
+struct PathIter<'a> {
+    caves: &'a Caves,
+    start: CaveID,
+    goal: CaveID,
+    limits: Limits,
+    max_length: usize,
+    todo: Vec<Path>,
+}
+
+impl Iterator for PathIter<'_> {
+    type Item = Result<Path>;
+
+    fn next(&mut self) -> Option<Result<Path>> {
+        while let Some(path) = self.todo.pop() {
+            let cave = path.last();
+
+            if cave == self.goal {
                 // Found the end, no need to check the exits
-                solutions.push(path);
-                continue 'todo;
+                return Some(Ok(path));
             }
 
-            if self.data[cave as usize].is_small {
-                // PART 1
-                // Don't re-enter a small room
-                //if path[..path.len() - 1].contains(&cave) {
-                //    continue 'todo;
-                //}
+            if path.0.len >= self.max_length {
+                // Nothing more to find after an error
+                self.todo.clear();
+                return Some(Err(too_long(self.caves, cave, self.max_length)));
+            }
 
-                // Never return to the starting room
-                if cave == start && path.len() > 1 {
-                    continue 'todo;
+            // Walk cave's exits, checking the visits to small caves on the way in
+            for &exit in &self.caves.data[cave as usize].exits {
+                if exit == self.start {
+                    // Never return to the starting room
+                    continue;
                 }
-
-                // Ensure the path isn't revisiting too many small rooms
-                let mut got_a_double = false;
-                let mut seen = HashSet::<CaveID>::new();
-                for &p in &path {
-                    if self.data[p as usize].is_small {
-                        if seen.contains(&p) {
-                            if got_a_double {
-                                // Already had a double visit; bail
-                                continue 'todo;
-                            }
-
-                            // This is the first double-visit
-                            got_a_double = true;
-                        } else {
-                            // First appearance of this small room
-                            seen.insert(p);
-                        }
-                    }
+                let visits = if self.caves.data[exit as usize].is_small {
+                    path.rev().filter(|&c| c == exit).count()
+                } else {
+                    0
+                };
+                if let Some(revisits) = self.limits.enter(exit, visits, path.0.revisits) {
+                    self.todo.push(path.push(exit, revisits));
                 }
+            }
+        }
 
-                if todo.len() > 1_000 || path.len() > 1_000 {
-                    for (i, c) in self.data.iter().enumerate() {
-                        eprintln!("{}: {} {} {:?}", i, c.name, c.is_small, &c.exits);
-                    }
+        None
+    }
+}
 
-                    for p in &todo {
-                        eprintln!("{:?}", p);
-                    }
+fn too_long(caves: &Caves, cave: CaveID, max_length: usize) -> anyhow::Error {
+    anyhow!(
+        "Path reached {max_length} caves at {:?}, are two big caves connected? \
+        If not, raise the limit with --max-length",
+        caves.data[cave as usize].name
+    )
+}
+
+// Bytes of visit counts the memo can hold before counting gives up. With a lot of small
+// caves the number of states can grow exponentially.
+const MAX_MEMO_BYTES: usize = 64 << 20;
+
+// A state in Caves::count: (cave, visit counts, revisits used)
+type CountKey = (CaveID, Box<[u8]>, usize);
+
+// Search state for Caves::count
+struct PathCounter<'a> {
+    caves: &'a Caves,
+    start: CaveID,
+    goal: CaveID,
+    limits: Limits,
+    // Index of each small cave's visit count in `counts`; None for big caves
+    slots: Vec<Option<usize>>,
+    // Visits to each small cave on the current path, up to the cave's limit
+    counts: Vec<u8>,
+    // Paths to the goal from each state. None while it's being counted, to catch paths
+    // that could go on forever.
+    memo: HashMap<CountKey, Option<usize>>,
+}
+
+// A state whose exits are still being counted
+struct CountFrame {
+    key: CountKey,
+    // Index of the next exit to follow
+    exit: usize,
+    // Paths through the exits so far
+    total: usize,
+    // The small cave whose visit count went up to enter this state, if any
+    entered: Option<usize>,
+}
 
-                    dbg!(&seen);
-                    dbg!(&got_a_double);
-                    unimplemented!("todo {}, path {}", todo.len(), path.len());
+impl CountFrame {
+    fn add(&mut self, count: usize) -> Result<()> {
+        self.total = self
+            .total
+            .checked_add(count)
+            .ok_or_else(|| anyhow!("Too many paths to count"))?;
+        Ok(())
+    }
+}
+
+enum Visit {
+    Known(usize),
+    New(CountFrame),
+}
+
+impl PathCounter<'_> {
+    // A depth first search with its own stack, since paths can be far longer than the
+    // call stack could recurse. Each state's count goes in the memo once all its exits
+    // are counted, and is added to the state before it.
+    fn count(&mut self) -> Result<usize> {
+        let mut stack = match self.visit(self.start, 0, None)? {
+            Visit::Known(count) => return Ok(count),
+            Visit::New(frame) => vec![frame],
+        };
+
+        let caves = self.caves;
+        while let Some(frame) = stack.last_mut() {
+            let (cave, used) = (frame.key.0, frame.key.2);
+            let Some(&exit) = caves.data[cave as usize].exits.get(frame.exit) else {
+                let frame = stack.pop().expect("Stack has a last frame");
+                if let Some(slot) = frame.entered {
+                    self.counts[slot] -= 1;
+                }
+                self.memo.insert(frame.key, Some(frame.total));
+                match stack.last_mut() {
+                    Some(before) => before.add(frame.total)?,
+                    None => return Ok(frame.total),
                 }
+                continue;
+            };
+            frame.exit += 1;
+            if exit == self.start {
+                // Never return to the starting room
+                continue;
             }
 
-            // Still here? OK, then walk cave's exits
-            todo.extend(self.data[cave as usize].exits.iter().map(|&e| {
-                let mut p = path.clone();
-                p.push(e);
-                p
-            }));
+            let (used, entered) = match self.slots[exit as usize] {
+                None => (used, None),
+                Some(slot) => {
+                    let visits = usize::from(self.counts[slot]);
+                    let Some(used) = self.limits.enter(exit, visits, used) else {
+                        continue;
+                    };
+                    if visits < self.limits.visits[exit as usize] {
+                        self.counts[slot] += 1;
+                        (used, Some(slot))
+                    } else {
+                        (used, None)
+                    }
+                }
+            };
+            match self.visit(exit, used, entered)? {
+                Visit::Known(count) => {
+                    if let Some(slot) = entered {
+                        self.counts[slot] -= 1;
+                    }
+                    stack
+                        .last_mut()
+                        .expect("Stack has a last frame")
+                        .add(count)?;
+                }
+                Visit::New(frame) => stack.push(frame),
+            }
         }
-
-        solutions
+        unreachable!("The first frame returns the total")
     }
 
-    fn exhaust(&self, start: &str, end: &str) -> Vec<Vec<CaveID>> {
-        // TODO Maybe put walk on an iterator struct, and store start & end there?
-        assert_eq!(start, "start");
-        assert_eq!(end, "end");
-
-        let start = *self.by_name.get(start).unwrap();
-        let end = *self.by_name.get(end).unwrap();
+    // Goes into a cave, with `counts` already updated for it. Either the paths from
+    // there are already known, or there's a new state to count.
+    fn visit(&mut self, cave: CaveID, used: usize, entered: Option<usize>) -> Result<Visit> {
+        if cave == self.goal {
+            return Ok(Visit::Known(1));
+        }
 
-        self.walk(start, end)
+        let key = (cave, Box::from(self.counts.as_slice()), used);
+        match self.memo.get(&key) {
+            Some(Some(count)) => return Ok(Visit::Known(*count)),
+            Some(None) => bail!(
+                "Endless paths through {:?}, are two big caves connected?",
+                self.caves.data[cave as usize].name
+            ),
+            None => {}
+        }
+        ensure!(
+            self.memo.len() * self.counts.len() < MAX_MEMO_BYTES,
+            "Too many states to count paths, try enumerating them"
+        );
+        self.memo.insert(key.clone(), None);
+        Ok(Visit::New(CountFrame {
+            key,
+            exit: 0,
+            total: 0,
+            entered,
+        }))
     }
 }
 
 #[derive(Debug)]
-#[allow(dead_code)]
 struct Cave {
     id: CaveID,
     name: String,
@@ -220,4 +701,45 @@ dhat: At t-end:  1,024 bytes in 1 blocks
 0.17user 0.00system 0:00.17elapsed 100%CPU (0avgtext+0avgdata 8384maxresident)k
 0inputs+0outputs (0major+1687minor)pagefaults 0swaps
 
+Counting with memoisation on (cave, small caves visited, double used), without building any
+paths (`--enumerate` still uses the iterative walk):
+Result: 107395
+dhat: Total:     71,716 bytes in 96 blocks
+dhat: At t-gmax: 52,590 bytes in 45 blocks
+
+0.00user 0.00system 0:00.00elapsed (release, no dhat) vs. 0.22s for `--enumerate`
+
+`--enumerate` with PathIter, where paths share their tails through Rc instead of being
+cloned for every exit, and are counted as they're found rather than collected:
+Result: 107395
+dhat: Total:     12,693,107 bytes in 317,295 blocks
+dhat: At t-gmax: 4,302 bytes in 94 blocks
+
+0.02user 0.00system 0:00.02elapsed (release, no dhat)
+
+Visit policies (`--visits`) and any two caves (`--from`, `--to`). Counting and enumerating
+agree for all of these:
+`--visits none` (part 1): 3679
+`--visits one-double` (part 2, default): 107395
+`--visits up-to=2`: 2101202
+`--visits up-to=3`: 34346743
+`--visits kl=3,eq=2`: 185174
+`--from XW --to ns`: 139757
+
+u32 cave IDs, and `--max-length N` (default 1,000) instead of the debug dump and
+`unimplemented!`. A generated ladder of 1,200 small caves:
+`day12 --input ladder.txt --visits none --max-length 5000 paths 3` finds paths of 603 and
+604 caves right away.
+
+Counting keeps a byte per small cave for its visits instead of packing them into a u128, so
+a chain of 202 caves (`start-a0`, ..., `a199-end`) counts its 1 path. On the ladder it gives
+up with "Too many states to count paths" once the memo holds 64M of visit counts.
+
+Counting uses its own stack instead of recursing, so it can't overflow the call stack, and
+it ignores `--max-length`. On `start-A`, `A-b`, `A-end` with `--visits up-to=30000` it
+gives 30002, which enumerating only reaches with a higher `--max-length`.
+
+Graphviz export: `day12 dot [FILE [PATH]]` writes the caves (default day12.dot), with path
+number PATH from `day12 paths` drawn in red. Render it with `dot -Tsvg day12.dot`.
+
 */